@@ -0,0 +1,58 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+use std::env;
+use std::fs;
+use std::path::Path;
+
+#[allow(dead_code)]
+#[path = "src/util.rs"]
+mod util;
+
+#[allow(dead_code)]
+#[path = "src/csr/registry.rs"]
+mod registry;
+
+#[allow(dead_code)]
+#[path = "src/csr/export.rs"]
+mod export;
+
+mod regs {
+	// Exported to the library as csr::regs::CSR_BLOCK_SIZE
+	pub const CSR_BLOCK_SIZE: u32 = 64;
+}
+
+// Same as csr::IMM_BITS
+const IMM_BITS: u8 = 18;
+
+use registry::{Registry, RegistryParser};
+use util::Width;
+
+const REGISTRY: &str = "src/csr/regs/regs.xml";
+
+fn load(path: &str) -> Registry {
+	let mut parser = RegistryParser::new();
+	parser.load_file(path).unwrap_or_else(|e| panic!("{e}"));
+	for file in parser.files() {
+		println!("cargo:rerun-if-changed={}", file.display());
+	}
+	let registry = parser.finish().unwrap_or_else(|e| panic!("{e}"));
+
+	let diagnostics = registry.validate();
+	if !diagnostics.is_empty() {
+		for diagnostic in &diagnostics {
+			println!("cargo:warning={path}: {diagnostic}");
+		}
+		panic!("{path} has {} errors", diagnostics.len());
+	}
+	registry
+}
+
+fn main() {
+	println!("cargo:rerun-if-changed=build.rs");
+	println!("cargo:rerun-if-changed=src/csr/registry.rs");
+	println!("cargo:rerun-if-changed=src/csr/export.rs");
+	println!("cargo:rerun-if-changed=src/util.rs");
+
+	let registry = load(REGISTRY);
+	let out = Path::new(&env::var("OUT_DIR").unwrap()).join("csr_regs.rs");
+	fs::write(&out, registry.to_rust()).unwrap_or_else(|e| panic!("Failed to write {}: {e}", out.display()));
+}
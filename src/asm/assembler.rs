@@ -0,0 +1,667 @@
+/* Copyright 2023 Robert Zieba, see LICENSE file for full license. */
+use std::collections::{BTreeMap, BTreeSet};
+use std::collections::btree_map::Entry;
+use std::fmt;
+
+use crate::{Encode, Width};
+use crate::csr::registry::Registry;
+
+use super::parse::{
+	ParseError,
+	ParseErrorKind,
+	Parser,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Symbol {
+	/// Address of a label, or an `.equ` defined as one
+	Label(u32),
+	/// Value defined with `.equ`
+	Constant(i64),
+}
+
+impl Symbol {
+	pub fn value(&self) -> i64 {
+		match self {
+			Symbol::Label(addr) => *addr as i64,
+			Symbol::Constant(value) => *value,
+		}
+	}
+}
+
+/// Error produced while assembling, `line` and `column` are 1-based
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssembleError {
+	pub line: usize,
+	pub column: usize,
+	pub kind: ParseErrorKind,
+}
+
+impl AssembleError {
+	fn new(line: usize, error: ParseError) -> AssembleError {
+		AssembleError {
+			line,
+			column: error.column,
+			kind: error.kind,
+		}
+	}
+}
+
+impl fmt::Display for AssembleError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}:{}: {}", self.line, self.column, self.kind)
+	}
+}
+
+impl std::error::Error for AssembleError {}
+
+/// Bytes emitted for a source line, lines without any have a size of zero
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Placement {
+	pub addr: u32,
+	pub size: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RelocationKind {
+	/// Address stored as data
+	Absolute(Width),
+	/// Jump to a constant address, its offset changes with the jump's address
+	Jump,
+}
+
+/// Field holding a value that depends on where the program is placed
+///
+/// Jumps between labels and differences of labels stay the same wherever the program is linked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Relocation {
+	/// Address of the field
+	pub addr: u32,
+	pub kind: RelocationKind,
+}
+
+/// Output of the assembler, data is packed into words in little endian byte order
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Program {
+	/// Address of the first word
+	pub origin: u32,
+	pub words: Vec<u32>,
+	pub symbols: BTreeMap<String, Symbol>,
+	/// Symbols named by `.global`, visible to other programs when linked
+	pub globals: BTreeSet<String>,
+	/// Ordered by address
+	pub relocations: Vec<Relocation>,
+	/// Placement of each source line, the first line is at index 0
+	pub lines: Vec<Placement>,
+}
+
+impl Program {
+	/// Contents as little endian bytes, padded to a whole number of words
+	pub fn bytes(&self) -> Vec<u8> {
+		self.words.iter().flat_map(|w| w.to_le_bytes()).collect()
+	}
+}
+
+/// Effect of a line on the location counter, computed in the first pass
+enum Layout {
+	Size(u32),
+	Align(u32),
+	Org(u32),
+	/// Name, the name's column and value
+	Equ(String, usize, Symbol),
+	/// Names exported by `.global` and their columns
+	Global(Vec<(String, usize)>),
+}
+
+const DATA_DIRECTIVES: [&str; 4] = [".word", ".half", ".byte", ".ascii"];
+
+/// Largest program in bytes, gaps between `.org`s are filled so this bounds their size as well
+const MAX_PROGRAM_SIZE: u32 = 16 << 20;
+
+/// Looks up a symbol, `.` is resolved by the parser
+fn resolve(symbols: &BTreeMap<String, Symbol>, name: &str) -> Option<Symbol> {
+	symbols.get(name).copied()
+}
+
+/// Adds a new symbol, returning false if the name is already taken
+fn define(symbols: &mut BTreeMap<String, Symbol>, name: String, symbol: Symbol) -> bool {
+	match symbols.entry(name) {
+		Entry::Vacant(entry) => {
+			entry.insert(symbol);
+			true
+		},
+		Entry::Occupied(_) => false,
+	}
+}
+
+/// Width of each value of a data directive and the range of values it accepts, `None` for `.ascii`
+fn data_width(directive: &str) -> Option<(Width, i64, i64)> {
+	match directive {
+		".word" => Some((Width::Word, i32::MIN as i64, u32::MAX as i64)),
+		".half" => Some((Width::Short, i16::MIN as i64, u16::MAX as i64)),
+		".byte" => Some((Width::Byte, i8::MIN as i64, u8::MAX as i64)),
+		_ => None,
+	}
+}
+
+/// Size of a data directive, values aren't checked since symbols defined later read as zero
+fn data_size(parser: &mut Parser, directive: &str) -> Result<u32, ParseError> {
+	let Some((width, _, _)) = data_width(directive) else {
+		return parser.string().map(|bytes| bytes.len() as u32);
+	};
+
+	let mut size = 0;
+	loop {
+		parser.expr()?;
+		size += width.to_len();
+		if !parser.eat_comma() {
+			return Ok(size);
+		}
+	}
+}
+
+/// Bytes emitted by a data directive at `pc`, any addresses in it are relocated
+fn data(parser: &mut Parser, directive: &str, pc: u32) -> Result<Vec<u8>, ParseError> {
+	let Some((width, min, max)) = data_width(directive) else {
+		return parser.string();
+	};
+
+	let mut bytes = Vec::new();
+	loop {
+		let (value, is_address) = parser.address_expr(min, max)?;
+		if is_address {
+			parser.relocate(pc.wrapping_add(bytes.len() as u32), RelocationKind::Absolute(width));
+		}
+		bytes.extend_from_slice(&value.to_le_bytes()[..width.to_len() as usize]);
+		if !parser.eat_comma() {
+			return Ok(bytes);
+		}
+	}
+}
+
+fn layout<'a>(parser: &mut Parser<'a>, strict: &'a dyn Fn(&str) -> Option<Symbol>, pc: u32) -> Result<Layout, ParseError> {
+	let (directive, column) = match parser.directive() {
+		Some(directive) => directive,
+		None if parser.is_empty() => return Ok(Layout::Size(0)),
+		None => return Ok(Layout::Size(4 * parser.instruction_count())),
+	};
+
+	if DATA_DIRECTIVES.contains(&directive.as_str()) {
+		// Errors are reported by the second pass once all symbols are known
+		let size = data_size(parser, &directive).unwrap_or(0);
+		return Ok(Layout::Size(size));
+	}
+
+	// Anything affecting the layout can only refer to symbols defined before it
+	parser.set_symbols(strict);
+	let layout = match directive.as_str() {
+		".align" => {
+			let column = parser.column();
+			let align = parser.ranged_expr(1, 1 << 31)?;
+			if !(align as u32).is_power_of_two() {
+				return Err(ParseError::new(ParseErrorKind::InvalidAlignment(align), column));
+			}
+			Layout::Align(align as u32)
+		},
+		".org" => {
+			let column = parser.column();
+			let requested = parser.ranged_expr(0, u32::MAX as i64)? as u32;
+			if requested < pc {
+				return Err(ParseError::new(ParseErrorKind::OriginBackwards { current: pc, requested }, column));
+			}
+			Layout::Org(requested)
+		},
+		".equ" => {
+			let (name, column) = parser.symbol_name()?;
+			if !parser.eat_comma() {
+				return Err(ParseError::new(ParseErrorKind::Expected("','"), parser.column()));
+			}
+			// Names for addresses are labels, so they're relocated like them
+			let symbol = match parser.address_expr(i64::MIN, i64::MAX)? {
+				(addr, true) => Symbol::Label(addr as u32),
+				(value, false) => Symbol::Constant(value),
+			};
+			Layout::Equ(name, column, symbol)
+		},
+		".global" | ".globl" => {
+			let mut names = vec![parser.symbol_name()?];
+			while parser.eat_comma() {
+				names.push(parser.symbol_name()?);
+			}
+			Layout::Global(names)
+		},
+		_ => return Err(ParseError::new(ParseErrorKind::UnknownDirective(directive), column)),
+	};
+
+	parser.finish()?;
+	Ok(layout)
+}
+
+/// Two pass assembler, the first pass assigns addresses to labels and the second encodes
+#[derive(Debug, Default)]
+pub struct Assembler {
+	registry: Option<Registry>,
+}
+
+impl Assembler {
+	pub fn new() -> Assembler {
+		Assembler {
+			registry: None,
+		}
+	}
+
+	/// Accepts register names and aliases from `registry` as CSR operands
+	pub fn with_registry(mut self, registry: Registry) -> Assembler {
+		self.registry = Some(registry);
+		self
+	}
+
+	/// Returns the symbol table, the exported symbols and the address of each line
+	fn first_pass(&self, source: &str, errors: &mut Vec<AssembleError>) -> (BTreeMap<String, Symbol>, BTreeSet<String>, Vec<u32>) {
+		let mut symbols = BTreeMap::new();
+		let mut addresses = Vec::new();
+		// Checked once every symbol is defined, with their lines
+		let mut globals = Vec::new();
+		let mut pc: u32 = 0;
+
+		for (index, text) in source.lines().enumerate() {
+			let line = index + 1;
+			addresses.push(pc);
+
+			let (labels, result) = {
+				let strict = |name: &str| resolve(&symbols, name);
+				let lenient = |name: &str| strict(name).or(Some(Symbol::Constant(0)));
+				let mut parser = match Parser::new(text, 1, &lenient) {
+					Ok(parser) => parser.at(pc),
+					// Reported by the second pass
+					Err(_) => continue,
+				};
+
+				let labels = parser.labels();
+				(labels, layout(&mut parser, &strict, pc))
+			};
+
+			for (name, column) in labels {
+				if !define(&mut symbols, name.clone(), Symbol::Label(pc)) {
+					errors.push(AssembleError::new(line, ParseError::new(ParseErrorKind::DuplicateSymbol(name), column)));
+				}
+			}
+
+			match result {
+				Ok(Layout::Size(size)) => pc = pc.wrapping_add(size),
+				Ok(Layout::Align(align)) => pc = pc.wrapping_add(align - 1) & !(align - 1),
+				Ok(Layout::Org(addr)) => pc = addr,
+				Ok(Layout::Equ(name, column, symbol)) => {
+					if !define(&mut symbols, name.clone(), symbol) {
+						errors.push(AssembleError::new(line, ParseError::new(ParseErrorKind::DuplicateSymbol(name), column)));
+					}
+				},
+				Ok(Layout::Global(names)) => globals.extend(names.into_iter().map(|(name, column)| (name, line, column))),
+				Err(error) => errors.push(AssembleError::new(line, error)),
+			}
+		}
+
+		// Only labels end up in object files
+		let globals = globals.into_iter().filter_map(|(name, line, column)| {
+			let kind = match symbols.get(&name) {
+				Some(Symbol::Label(_)) => return Some(name),
+				Some(Symbol::Constant(_)) => ParseErrorKind::Expected("label"),
+				None => ParseErrorKind::UnknownSymbol(name),
+			};
+			errors.push(AssembleError::new(line, ParseError::new(kind, column)));
+			None
+		}).collect();
+
+		(symbols, globals, addresses)
+	}
+
+	/// Returns the origin, the program's bytes, its relocations and the placement of each line
+	fn second_pass(&self, source: &str, symbols: &BTreeMap<String, Symbol>, addresses: &[u32], errors: &mut Vec<AssembleError>) -> (u32, Vec<u8>, Vec<Relocation>, Vec<Placement>) {
+		let mut origin = None;
+		let mut bytes = Vec::new();
+		let mut relocations = Vec::new();
+		let mut lines = Vec::new();
+
+		for ((index, text), &pc) in source.lines().enumerate().zip(addresses) {
+			let line = index + 1;
+			let strict = |name: &str| resolve(symbols, name);
+
+			let result = Parser::new(text, 1, &strict).and_then(|parser| {
+				let mut parser = parser.at(pc).with_registry(self.registry.as_ref());
+				parser.labels();
+				let column = parser.column();
+
+				let data = match parser.directive() {
+					Some((directive, _)) if DATA_DIRECTIVES.contains(&directive.as_str()) => data(&mut parser, &directive, pc)?,
+					// Handled by the first pass
+					Some(_) => return Ok(Default::default()),
+					None if parser.is_empty() => return Ok(Default::default()),
+					None => {
+						if pc % 4 != 0 {
+							return Err(ParseError::new(ParseErrorKind::UnalignedInstruction(pc), parser.column()));
+						}
+						parser.instructions()?.iter().flat_map(|i| i.encode().to_le_bytes()).collect()
+					},
+				};
+
+				parser.finish()?;
+				if let Some(origin) = origin.filter(|_| !data.is_empty()) {
+					let end = pc.wrapping_sub(origin).checked_add(data.len() as u32);
+					if end.is_none_or(|end| end > MAX_PROGRAM_SIZE) {
+						return Err(ParseError::new(ParseErrorKind::TooFarFromOrigin { origin, addr: pc }, column));
+					}
+				}
+				Ok((data, parser.take_relocations()))
+			});
+
+			let size = result.as_ref().map_or(0, |(data, _)| data.len() as u32);
+			lines.push(Placement { addr: pc, size });
+
+			match result {
+				Ok((data, line_relocations)) if !data.is_empty() => {
+					let origin = *origin.get_or_insert(pc);
+					// Fill any gap left by .org or .align
+					bytes.resize(pc.wrapping_sub(origin) as usize, 0);
+					bytes.extend_from_slice(&data);
+					relocations.extend(line_relocations);
+				},
+				Ok(_) => {},
+				Err(error) => errors.push(AssembleError::new(line, error)),
+			}
+		}
+
+		(origin.unwrap_or(0), bytes, relocations, lines)
+	}
+
+	pub fn assemble(&self, source: &str) -> Result<Program, Vec<AssembleError>> {
+		let mut errors = Vec::new();
+		let (symbols, globals, addresses) = self.first_pass(source, &mut errors);
+		let (origin, bytes, relocations, lines) = self.second_pass(source, &symbols, &addresses, &mut errors);
+
+		if !errors.is_empty() {
+			errors.sort_by_key(|e| (e.line, e.column));
+			return Err(errors);
+		}
+
+		let words = bytes.chunks(4).map(|chunk| {
+			let mut word = [0u8; 4];
+			word[..chunk.len()].copy_from_slice(chunk);
+			u32::from_le_bytes(word)
+		}).collect();
+
+		Ok(Program {
+			origin,
+			words,
+			symbols,
+			globals,
+			relocations,
+			lines,
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::{
+		Instruction,
+		jump,
+	};
+
+	fn assemble(source: &str) -> Program {
+		Assembler::new().assemble(source).unwrap()
+	}
+
+	fn errors(source: &str) -> Vec<(usize, usize, ParseErrorKind)> {
+		Assembler::new().assemble(source).unwrap_err()
+			.into_iter()
+			.map(|e| (e.line, e.column, e.kind))
+			.collect()
+	}
+
+	#[test]
+	fn labels() {
+		let program = assemble("
+			.org 0x100
+		start:
+			add r1, r0, 1
+		loop: sub r1, r1, 1
+			j loop
+			j end
+		end: j start
+		");
+
+		assert_eq!(program.origin, 0x100);
+		assert_eq!(program.symbols["start"], Symbol::Label(0x100));
+		assert_eq!(program.symbols["loop"], Symbol::Label(0x104));
+		assert_eq!(program.symbols["end"], Symbol::Label(0x110));
+
+		let jumps: Vec<_> = program.words[2..].iter()
+			.map(|w| Instruction::decode(*w).unwrap())
+			.collect();
+		assert_eq!(jumps, vec![
+			Instruction::Jump(jump::Instruction { imm: -4 }),
+			Instruction::Jump(jump::Instruction { imm: 4 }),
+			Instruction::Jump(jump::Instruction { imm: -0x10 }),
+		]);
+	}
+
+	#[test]
+	fn jump_operands() {
+		let jump = |source: &str, index: usize| match Instruction::decode(assemble(source).words[index]).unwrap() {
+			Instruction::Jump(j) => j.imm,
+			i => panic!("{i} isn't a jump"),
+		};
+
+		// Operands are target addresses, whether labels, constants, numbers or differences
+		assert_eq!(jump(".org 0x100\nstart: j end - start\nend:", 0), 4 - 0x100);
+		assert_eq!(jump(".equ TARGET, 0x40\n.org 0x100\nj TARGET", 0), 0x40 - 0x100);
+		assert_eq!(jump(".org 0x100\nj 0x40", 0), 0x40 - 0x100);
+		assert_eq!(jump(".org 0x100\nj .+8", 0), 8);
+		assert_eq!(jump(".org 0x100\nj -4", 0), -4 - 0x100);
+
+		// Offsets wrap around the address space
+		assert_eq!(jump("j far\n.org 0xfffffff0\nfar:", 0), -0x10);
+		assert_eq!(jump(".org 0xfffffff0\nj far\n.equ far, 0x10", 0), 0x20);
+	}
+
+	#[test]
+	fn calls() {
+		let program = assemble("
+			.org 0x100
+		back:
+			call fwd
+			CALL back
+			call .+8
+		fwd: call .-4
+		");
+
+		assert_eq!(program.symbols["fwd"], Symbol::Label(0x118));
+		let listing: Vec<String> = program.words.iter()
+			.map(|w| Instruction::decode(*w).unwrap().to_string())
+			.collect();
+		assert_eq!(listing, [
+			"add r30, r31, 4", "j .+20",
+			"add r30, r31, 4", "j .-12",
+			"add r30, r31, 4", "j .+4",
+			"add r30, r31, 4", "j .-8",
+		]);
+
+		assert_eq!(errors("call 6"), vec![(1, 6, ParseErrorKind::Misaligned(6))]);
+	}
+
+	#[test]
+	fn indirect() {
+		let program = assemble("
+			jalr lr, r5, 8
+			jr r5
+		end: ret
+		");
+
+		assert_eq!(program.symbols["end"], Symbol::Label(0xc));
+		let listing: Vec<String> = program.words.iter()
+			.map(|w| Instruction::decode(*w).unwrap().to_string())
+			.collect();
+		assert_eq!(listing, ["add r30, r31, 4", "add r31, r5, 8", "add r31, r5, 0", "add r31, r30, 0"]);
+
+		assert_eq!(errors("jalr r5, r5, 0"), vec![
+			(1, 6, ParseErrorKind::LinkOverwritesTarget(crate::Register::r5())),
+		]);
+		assert_eq!(errors("jalr pc, r5, 0"), vec![(1, 6, ParseErrorKind::LinkToPc)]);
+	}
+
+	#[test]
+	fn directives() {
+		let program = assemble("
+			.equ SIZE, 8
+			add r1, r0, SIZE - 1
+			.byte 1, 2, -1
+			.align 4
+			.half 0xbeef
+			.ascii \"hi\"
+			.org 0x10
+		data: .word data, -1
+		");
+
+		assert_eq!(program.symbols["SIZE"], Symbol::Constant(8));
+		assert_eq!(program.lines[1..5], [
+			Placement { addr: 0, size: 0 },
+			Placement { addr: 0, size: 4 },
+			Placement { addr: 4, size: 3 },
+			Placement { addr: 7, size: 0 },
+		]);
+		assert_eq!(program.lines[8], Placement { addr: 0x10, size: 8 });
+		assert_eq!(program.words[0], crate::asm::parse_line("add r1, r0, 7").unwrap().encode());
+		assert_eq!(&program.words[1..], &[0x00ff0201, 0x6968beef, 0, 0x10, 0xffffffff]);
+	}
+
+	#[test]
+	fn symbol_errors() {
+		assert_eq!(errors("a:\na: j a\nj b"), vec![
+			(2, 1, ParseErrorKind::DuplicateSymbol("a".into())),
+			(3, 3, ParseErrorKind::UnknownSymbol("b".into())),
+		]);
+
+		assert_eq!(errors(".equ X, 1\n.equ X, 2"), vec![
+			(2, 6, ParseErrorKind::DuplicateSymbol("X".into())),
+		]);
+
+		// The name also appears earlier on the line
+		assert_eq!(errors(".equ e, 1
+  .equ  e, 2"), vec![
+			(2, 9, ParseErrorKind::DuplicateSymbol("e".into())),
+		]);
+		assert_eq!(errors("q: .equ q, 1"), vec![
+			(1, 9, ParseErrorKind::DuplicateSymbol("q".into())),
+		]);
+
+		assert_eq!(errors(".org END\nEND:"), vec![
+			(1, 6, ParseErrorKind::UnknownSymbol("END".into())),
+		]);
+	}
+
+	#[test]
+	fn relocations() {
+		let program = assemble("
+			.global start
+			.equ OUT, 0x80
+		start:
+			call 0x40
+			call start
+			j OUT
+		.equ ALIAS, start + 4
+			.byte ALIAS, ALIAS - start
+		");
+		assert_eq!(program.symbols["ALIAS"], Symbol::Label(4));
+		assert_eq!(program.globals, BTreeSet::from(["start".into()]));
+		assert_eq!(program.relocations, [
+			Relocation { addr: 0x4, kind: RelocationKind::Jump },
+			Relocation { addr: 0x10, kind: RelocationKind::Jump },
+			Relocation { addr: 0x14, kind: RelocationKind::Absolute(Width::Byte) },
+		]);
+
+		assert_eq!(errors("a: .word a + a"), vec![(1, 10, ParseErrorKind::NotRelocatable)]);
+		assert_eq!(errors("a: j -a"), vec![(1, 6, ParseErrorKind::NotRelocatable)]);
+		assert_eq!(errors(".global a, B
+.equ B, 1"), vec![
+			(1, 9, ParseErrorKind::UnknownSymbol("a".into())),
+			(1, 12, ParseErrorKind::Expected("label")),
+		]);
+	}
+
+	#[test]
+	fn range_errors() {
+		assert_eq!(errors(".equ BIG, 4096\nadd r1, r2, BIG"), vec![
+			(2, 13, ParseErrorKind::OutOfRange { value: 4096, min: -2048, max: 2047 }),
+		]);
+
+		assert_eq!(errors("j 0x100000000"), vec![
+			(1, 3, ParseErrorKind::OutOfRange { value: 0x100000000, min: i32::MIN as i64, max: u32::MAX as i64 }),
+		]);
+
+		assert_eq!(errors("add r1, r0, 1\n.org 0xfffffff0\n.word 1"), vec![
+			(3, 1, ParseErrorKind::TooFarFromOrigin { origin: 0, addr: 0xfffffff0 }),
+		]);
+
+		assert_eq!(errors(".byte 256\n.byte 1\nnop_label: add r1, r2, r3"), vec![
+			(1, 7, ParseErrorKind::OutOfRange { value: 256, min: -128, max: 255 }),
+			(3, 12, ParseErrorKind::UnalignedInstruction(2)),
+		]);
+	}
+
+	#[test]
+	fn csr_names() {
+		use crate::csr::regs::*;
+
+		let assembler = Assembler::new().with_registry(Registry::builtin());
+		let program = assembler.assemble("
+			.equ ERR, 0x44
+			csr.ld r4, isr.err1
+			csr.st.b r1, dbg_out.char_out0
+			csr.ld r2, CSR_PSR
+			csr.ld r3, isr_err2
+			csr.ld r3, ERR
+			csr.ld r3, 0x48
+		").unwrap();
+
+		let addresses: Vec<u32> = program.words.iter().map(|w| match Instruction::decode(*w).unwrap() {
+			Instruction::Csr(i) => i.imm,
+			i => panic!("unexpected {i}"),
+		}).collect();
+		assert_eq!(addresses, [ISR_ERR1_REG, DBG_OUT_CHAR_OUT0_REG, CSR_PSR_REG, ISR_ERR2_REG, 0x44, 0x48]);
+
+		let registry = Registry::builtin();
+		let listing: Vec<String> = program.words.iter()
+			.map(|w| Instruction::decode(*w).unwrap().display_with(&registry).to_string())
+			.collect();
+		assert_eq!(listing[..2], ["csr.ld r4, isr.err1", "csr.st.b r1, dbg_out.char_out0"]);
+		for line in &listing {
+			let words = assembler.assemble(line).unwrap().words;
+			assert_eq!(Instruction::decode(words[0]).unwrap().display_with(&registry).to_string(), *line);
+		}
+
+		let errors: Vec<_> = assembler.assemble("csr.ld r4, isr.err3\ncsr.ld r1, dbg_out.char_in0\ncsr.ld r1, nothing")
+			.unwrap_err()
+			.into_iter()
+			.map(|e| (e.line, e.column, e.kind))
+			.collect();
+		assert_eq!(errors, vec![
+			(1, 12, ParseErrorKind::UnknownCsr("isr.err3".into())),
+			(2, 12, ParseErrorKind::CsrWidthMismatch {
+				name: "dbg_out.char_in0".into(),
+				expected: crate::Width::Byte,
+				found: crate::Width::Word,
+			}),
+			(3, 12, ParseErrorKind::UnknownSymbol("nothing".into())),
+		]);
+	}
+
+	#[test]
+	fn layout_errors() {
+		assert_eq!(errors(".org 8\n.org 4"), vec![
+			(2, 6, ParseErrorKind::OriginBackwards { current: 8, requested: 4 }),
+		]);
+		assert_eq!(errors(".align 3"), vec![(1, 8, ParseErrorKind::InvalidAlignment(3))]);
+		assert_eq!(errors(".foo 3"), vec![(1, 1, ParseErrorKind::UnknownDirective(".foo".into()))]);
+	}
+}
@@ -0,0 +1,145 @@
+/* Copyright 2023 Robert Zieba, see LICENSE file for full license. */
+use crate::{
+    BinOp,
+    Instruction,
+    LoadStore,
+    LoadStoreOp,
+    Register,
+    rri,
+    rrr,
+    Shift,
+    ShiftKind,
+    Width,
+    csr,
+};
+
+mod assembler;
+mod parse;
+
+pub use assembler::{
+    AssembleError,
+    Assembler,
+    Placement,
+    Program,
+    Relocation,
+    RelocationKind,
+    Symbol,
+};
+pub use parse::{
+    parse_line,
+    ParseError,
+    ParseErrorKind,
+};
+
+pub fn add_rs(rd :Register, rs: Register, rq: Register, shift: Option<Shift>) -> Instruction {
+    Instruction::Rrr(rrr::Instruction {
+        op: BinOp::Add,
+        dest: rd,
+        lhs: rs,
+        rhs: rq,
+        shift: shift.unwrap_or(Shift {
+            kind: ShiftKind::Shl,
+            shift: 0,
+        }),
+    })
+}
+
+pub fn add_r(rd :Register, rs: Register, rq: Register) -> Instruction {
+    add_rs(rd, rs, rq, None)
+}
+
+pub fn nop() -> Instruction {
+    add_r(Register::r0(), Register::r0(), Register::r1())
+}
+
+fn add_i(rd: Register, rs: Register, imm: i16) -> Instruction {
+    Instruction::Rri(rri::Instruction {
+        op: BinOp::Add,
+        cond: rri::Condition::Always,
+        dest: rd,
+        src: rs,
+        imm,
+    })
+}
+
+/// Jumps to the address in `rs`
+pub fn jr(rs: Register) -> Instruction {
+    add_i(Register::pc(), rs, 0)
+}
+
+/// Returns to the address saved in `lr` by a call
+pub fn ret() -> Instruction {
+    jr(Register::lr())
+}
+
+/// Saves the return address in `rd` and jumps to `rs + imm`
+///
+/// `rd` must differ from `rs` since it's written before the jump reads `rs`, and can't be the PC
+/// since writing it would branch before the jump.
+pub fn jalr(rd: Register, rs: Register, imm: i16) -> [Instruction; 2] {
+    [add_i(rd, Register::pc(), 4), add_i(Register::pc(), rs, imm)]
+}
+
+fn isr_move(op: LoadStore, regs: &[Register]) -> Vec<Instruction> {
+    regs.iter()
+        .filter_map(|&reg| csr::isr_save_slot(reg).map(|imm| (reg, imm)))
+        .map(|(reg, imm)| Instruction::Csr(csr::Instruction {
+            op: LoadStoreOp {
+                op,
+                width: Width::Word,
+            },
+            reg,
+            imm,
+        }))
+        .collect()
+}
+
+/// Stores each of `regs` to its ISR save slot, `r0` has no slot and is skipped
+pub fn isr_save(regs: &[Register]) -> Vec<Instruction> {
+    isr_move(LoadStore::Store, regs)
+}
+
+/// Loads each of `regs` from its ISR save slot, `r0` has no slot and is skipped
+pub fn isr_restore(regs: &[Register]) -> Vec<Instruction> {
+    isr_move(LoadStore::Load, regs)
+}
+
+/// Registers making up a full context, the PC isn't included since loading it would branch
+pub fn isr_context() -> Vec<Register> {
+    (1..31).filter_map(Register::new).collect()
+}
+
+pub fn isr_save_context() -> Vec<Instruction> {
+    isr_save(&isr_context())
+}
+
+pub fn isr_restore_context() -> Vec<Instruction> {
+    isr_restore(&isr_context())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::csr::registry::Registry;
+
+    #[test]
+    fn isr_context_moves() {
+        let registry = Registry::builtin();
+        let listing = |instructions: Vec<Instruction>| -> Vec<String> {
+            instructions.iter().map(|i| i.display_with(&registry).to_string()).collect()
+        };
+
+        let save = listing(isr_save_context());
+        assert_eq!(save.len(), 30);
+        assert_eq!(save[0], "csr.st r1, isr.r1");
+        assert_eq!(save[26], "csr.st r27, isr.r27");
+        assert_eq!(save[29], "csr.st r30, isr.lr");
+
+        let restore = listing(isr_restore_context());
+        assert_eq!(restore.len(), 30);
+        assert_eq!(restore[27], "csr.ld r28, isr.sp");
+
+        let partial = listing(isr_save(&[Register::r0(), Register::r5(), Register::pc()]));
+        assert_eq!(partial, ["csr.st r5, isr.r5", "csr.st r31, isr.pc"]);
+    }
+}
@@ -0,0 +1,919 @@
+/* Copyright 2023 Robert Zieba, see LICENSE file for full license. */
+use std::fmt;
+
+use num_traits::FromPrimitive;
+
+use crate::{
+	BinOp,
+	Condition,
+	DecodeError,
+	Encode,
+	Instruction,
+	LoadStore,
+	LoadStoreOp,
+	Register,
+	Shift,
+	ShiftKind,
+	Width,
+	csr::{
+		self,
+		registry::Registry,
+	},
+	jump,
+	memory,
+	rri,
+	rrr,
+};
+
+use super::{
+	Relocation,
+	RelocationKind,
+	Symbol,
+};
+
+const RRI_IMM_MIN: i64 = -(1 << (rri::IMM_BITS - 1));
+const RRI_IMM_MAX: i64 = (1 << (rri::IMM_BITS - 1)) - 1;
+const MEM_IMM_MIN: i64 = -(1 << (memory::ri::IMM_BITS - 1));
+const MEM_IMM_MAX: i64 = (1 << (memory::ri::IMM_BITS - 1)) - 1;
+const CSR_IMM_MAX: i64 = (1 << csr::IMM_BITS) - 1;
+const SHIFT_MAX: i64 = 31;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+	UnexpectedCharacter(char),
+	UnexpectedEnd,
+	Expected(&'static str),
+	InvalidNumber(String),
+	UnknownMnemonic(String),
+	UnknownCondition(String),
+	UnknownWidth(String),
+	UnknownRegister(String),
+	UnknownSymbol(String),
+	/// A condition suffix was given to an instruction without an immediate operand
+	UnexpectedCondition,
+	OutOfRange {
+		value: i64,
+		min: i64,
+		max: i64,
+	},
+	Misaligned(i64),
+	/// An expression's value doesn't fit in 64 bits
+	Overflow,
+	TrailingInput,
+	UnterminatedString,
+	UnknownDirective(String),
+	DuplicateSymbol(String),
+	InvalidAlignment(i64),
+	/// `.org` tried to move the location counter backwards
+	OriginBackwards {
+		current: u32,
+		requested: u32,
+	},
+	UnalignedInstruction(u32),
+	/// Data placed so far past the first emitted byte that the gap can't reasonably be filled
+	TooFarFromOrigin {
+		origin: u32,
+		addr: u32,
+	},
+	/// A raw `.word` doesn't decode to an instruction
+	InvalidEncoding(DecodeError),
+	UnknownCsr(String),
+	/// `jalr` would overwrite the jump's base register with the return address
+	LinkOverwritesTarget(Register),
+	/// `jalr` linking into the PC, which branches before the jump runs
+	LinkToPc,
+	/// CSR accessed with a different width than the register has
+	CsrWidthMismatch {
+		name: String,
+		expected: Width,
+		found: Width,
+	},
+	/// An address operand that isn't a constant, an address or the difference of two addresses,
+	/// so it can't be relocated when the program is linked
+	NotRelocatable,
+}
+
+/// Error produced while parsing assembly, `column` is the 1-based character position in the line
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+	pub kind: ParseErrorKind,
+	pub column: usize,
+}
+
+impl ParseError {
+	pub fn new(kind: ParseErrorKind, column: usize) -> ParseError {
+		ParseError {
+			kind,
+			column,
+		}
+	}
+}
+
+impl fmt::Display for ParseErrorKind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ParseErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character '{c}'"),
+			ParseErrorKind::UnexpectedEnd => write!(f, "unexpected end of line"),
+			ParseErrorKind::Expected(what) => write!(f, "expected {what}"),
+			ParseErrorKind::InvalidNumber(s) => write!(f, "invalid number '{s}'"),
+			ParseErrorKind::UnknownMnemonic(s) => write!(f, "unknown mnemonic '{s}'"),
+			ParseErrorKind::UnknownCondition(s) => write!(f, "unknown condition '{s}'"),
+			ParseErrorKind::UnknownWidth(s) => write!(f, "unknown width '{s}'"),
+			ParseErrorKind::UnknownRegister(s) => write!(f, "unknown register '{s}'"),
+			ParseErrorKind::UnknownSymbol(s) => write!(f, "undefined symbol '{s}'"),
+			ParseErrorKind::UnexpectedCondition => write!(f, "condition suffix requires an immediate operand"),
+			ParseErrorKind::OutOfRange { value, min, max } => write!(f, "value {value} out of range [{min}, {max}]"),
+			ParseErrorKind::Misaligned(value) => write!(f, "offset {value} is not a multiple of 4"),
+			ParseErrorKind::Overflow => write!(f, "expression overflows 64 bits"),
+			ParseErrorKind::TrailingInput => write!(f, "unexpected trailing input"),
+			ParseErrorKind::UnterminatedString => write!(f, "unterminated string"),
+			ParseErrorKind::UnknownDirective(s) => write!(f, "unknown directive '{s}'"),
+			ParseErrorKind::DuplicateSymbol(s) => write!(f, "duplicate symbol '{s}'"),
+			ParseErrorKind::InvalidAlignment(value) => write!(f, "alignment {value} is not a power of two"),
+			ParseErrorKind::TooFarFromOrigin { origin, addr } =>
+				write!(f, "address {addr:#x} is too far past the program's start at {origin:#x}"),
+			ParseErrorKind::OriginBackwards { current, requested } =>
+				write!(f, "cannot move location counter backwards from {current:#x} to {requested:#x}"),
+			ParseErrorKind::UnalignedInstruction(addr) => write!(f, "instruction at unaligned address {addr:#x}"),
+			ParseErrorKind::InvalidEncoding(err) => write!(f, "{err}"),
+			ParseErrorKind::UnknownCsr(s) => write!(f, "unknown CSR '{s}'"),
+			ParseErrorKind::LinkOverwritesTarget(reg) => write!(f, "link register {reg} is also the jump target"),
+			ParseErrorKind::LinkToPc => write!(f, "link register can't be the pc"),
+			ParseErrorKind::NotRelocatable => write!(f, "expression combines addresses in a way that can't be relocated"),
+			ParseErrorKind::CsrWidthMismatch { name, expected, found } =>
+				write!(f, "CSR '{name}' has width '{expected}' but is accessed with '{found}'"),
+		}
+	}
+}
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}: {}", self.column, self.kind)
+	}
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Token {
+	Ident(String),
+	Number(i64),
+	Comma,
+	LBracket,
+	RBracket,
+	Plus,
+	Minus,
+	ShiftLeft,
+	ShiftRight,
+	Colon,
+	Str(Vec<u8>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Lexeme {
+	pub token: Token,
+	pub column: usize,
+}
+
+fn is_ident_start(c: char) -> bool {
+	c.is_ascii_alphabetic() || c == '_' || c == '.'
+}
+
+fn is_ident_char(c: char) -> bool {
+	c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+fn parse_number(text: &str, column: usize) -> Result<i64, ParseError> {
+	let lower = text.to_ascii_lowercase();
+	let (digits, radix) = if let Some(hex) = lower.strip_prefix("0x") {
+		(hex, 16)
+	} else if let Some(bin) = lower.strip_prefix("0b") {
+		(bin, 2)
+	} else {
+		(lower.as_str(), 10)
+	};
+
+	i64::from_str_radix(digits, radix)
+		.map_err(|_| ParseError::new(ParseErrorKind::InvalidNumber(text.into()), column))
+}
+
+fn string(chars: &[char], start: usize, offset: usize) -> Result<(Vec<u8>, usize), ParseError> {
+	let mut bytes = Vec::new();
+	let mut i = start + 1;
+	let unterminated = ParseError::new(ParseErrorKind::UnterminatedString, offset + start);
+
+	loop {
+		let c = *chars.get(i).ok_or(unterminated.clone())?;
+		i += 1;
+		match c {
+			'"' => return Ok((bytes, i)),
+			'\\' => {
+				let escape = *chars.get(i).ok_or(unterminated.clone())?;
+				i += 1;
+				let byte = match escape {
+					'n' => b'\n',
+					't' => b'\t',
+					'r' => b'\r',
+					'0' => 0,
+					'\\' => b'\\',
+					'"' => b'"',
+					_ => return Err(ParseError::new(ParseErrorKind::UnexpectedCharacter(escape), offset + i - 1)),
+				};
+				bytes.push(byte);
+			},
+			c => {
+				let mut buf = [0u8; 4];
+				bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+			},
+		}
+	}
+}
+
+/// Splits `line` into tokens, stopping at a `;` comment, `offset` is the column of the first character
+pub(crate) fn tokenize(line: &str, offset: usize) -> Result<Vec<Lexeme>, ParseError> {
+	let chars: Vec<char> = line.chars().collect();
+	let mut tokens = Vec::new();
+	let mut i = 0;
+
+	while i < chars.len() {
+		let c = chars[i];
+		let column = offset + i;
+		if c == ';' {
+			break;
+		}
+
+		if c.is_whitespace() {
+			i += 1;
+			continue;
+		}
+
+		let token = if is_ident_start(c) {
+			let start = i;
+			while i < chars.len() && is_ident_char(chars[i]) {
+				i += 1;
+			}
+			Token::Ident(chars[start..i].iter().collect())
+		} else if c.is_ascii_digit() {
+			let start = i;
+			while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+				i += 1;
+			}
+			let text: String = chars[start..i].iter().collect();
+			Token::Number(parse_number(&text, column)?)
+		} else if c == '"' {
+			let (bytes, end) = string(&chars, i, offset)?;
+			i = end;
+			Token::Str(bytes)
+		} else {
+			let next = chars.get(i + 1).copied();
+			let (token, len) = match (c, next) {
+				(',', _) => (Token::Comma, 1),
+				('[', _) => (Token::LBracket, 1),
+				(']', _) => (Token::RBracket, 1),
+				('+', _) => (Token::Plus, 1),
+				('-', _) => (Token::Minus, 1),
+				(':', _) => (Token::Colon, 1),
+				('<', Some('<')) => (Token::ShiftLeft, 2),
+				('>', Some('>')) => (Token::ShiftRight, 2),
+				_ => return Err(ParseError::new(ParseErrorKind::UnexpectedCharacter(c), column)),
+			};
+			i += len;
+			token
+		};
+
+		tokens.push(Lexeme {
+			token,
+			column,
+		});
+	}
+
+	Ok(tokens)
+}
+
+/// Looks up a value by its canonical `Display` text so parsing always mirrors printing
+fn from_display<T: FromPrimitive + fmt::Display>(text: &str, count: u32) -> Option<T> {
+	(0..count)
+		.filter_map(T::from_u32)
+		.find(|value| value.to_string() == text)
+}
+
+fn binop_from_str(text: &str) -> Option<BinOp> {
+	from_display(text, 32)
+}
+
+fn condition_from_str(text: &str) -> Option<Condition> {
+	from_display(text, 8)
+}
+
+fn width_from_str(text: &str) -> Option<Width> {
+	from_display(text, 4)
+}
+
+fn shift_kind_from_str(text: &str) -> Option<ShiftKind> {
+	match text {
+		"shl" => Some(ShiftKind::Shl),
+		"shr" => Some(ShiftKind::Shr),
+		_ => from_display(text, 8),
+	}
+}
+
+pub(crate) struct Parser<'a> {
+	tokens: Vec<Lexeme>,
+	pos: usize,
+	end: usize,
+	symbols: &'a dyn Fn(&str) -> Option<Symbol>,
+	/// Address of the line being parsed, the value of `.`
+	pc: u32,
+	/// Names accepted as CSR operands
+	registry: Option<&'a Registry>,
+	/// Address operands found so far
+	relocations: Vec<Relocation>,
+}
+
+impl<'a> Parser<'a> {
+	/// Creates a parser over `line`, `offset` is the column of the first character of `line`
+	pub fn new(line: &str, offset: usize, symbols: &'a dyn Fn(&str) -> Option<Symbol>) -> Result<Parser<'a>, ParseError> {
+		Ok(Parser {
+			tokens: tokenize(line, offset)?,
+			pos: 0,
+			end: offset + line.chars().count(),
+			symbols,
+			pc: 0,
+			registry: None,
+			relocations: Vec::new(),
+		})
+	}
+
+	pub fn at(mut self, pc: u32) -> Parser<'a> {
+		self.pc = pc;
+		self
+	}
+
+	pub fn with_registry(mut self, registry: Option<&'a Registry>) -> Parser<'a> {
+		self.registry = registry;
+		self
+	}
+
+	/// Changes how symbols are resolved for the rest of the line
+	pub fn set_symbols(&mut self, symbols: &'a dyn Fn(&str) -> Option<Symbol>) {
+		self.symbols = symbols;
+	}
+
+	/// Records a use of an address at `addr`, which changes if the program is linked elsewhere
+	pub fn relocate(&mut self, addr: u32, kind: RelocationKind) {
+		self.relocations.push(Relocation { addr, kind });
+	}
+
+	pub fn take_relocations(&mut self) -> Vec<Relocation> {
+		std::mem::take(&mut self.relocations)
+	}
+
+	pub fn column(&self) -> usize {
+		self.tokens.get(self.pos).map_or(self.end, |l| l.column)
+	}
+
+	fn peek(&self) -> Option<&Token> {
+		self.tokens.get(self.pos).map(|l| &l.token)
+	}
+
+	fn next(&mut self) -> Result<Lexeme, ParseError> {
+		let lexeme = self.tokens.get(self.pos)
+			.cloned()
+			.ok_or(ParseError::new(ParseErrorKind::UnexpectedEnd, self.end))?;
+		self.pos += 1;
+		Ok(lexeme)
+	}
+
+	fn eat(&mut self, token: &Token) -> bool {
+		if self.peek() == Some(token) {
+			self.pos += 1;
+			true
+		} else {
+			false
+		}
+	}
+
+	fn expect(&mut self, token: Token, what: &'static str) -> Result<(), ParseError> {
+		let column = self.column();
+		if self.eat(&token) {
+			Ok(())
+		} else if self.pos >= self.tokens.len() {
+			Err(ParseError::new(ParseErrorKind::UnexpectedEnd, column))
+		} else {
+			Err(ParseError::new(ParseErrorKind::Expected(what), column))
+		}
+	}
+
+	pub fn eat_comma(&mut self) -> bool {
+		self.eat(&Token::Comma)
+	}
+
+	/// Consumes any `name:` label definitions at the start of the line
+	pub fn labels(&mut self) -> Vec<(String, usize)> {
+		let mut labels = Vec::new();
+		while let (Some(Lexeme { token: Token::Ident(name), column }), Some(Token::Colon)) =
+			(self.tokens.get(self.pos), self.tokens.get(self.pos + 1).map(|l| &l.token)) {
+			labels.push((name.clone(), *column));
+			self.pos += 2;
+		}
+		labels
+	}
+
+	/// Consumes a `.directive` name if one is next
+	pub fn directive(&mut self) -> Option<(String, usize)> {
+		match self.tokens.get(self.pos) {
+			Some(Lexeme { token: Token::Ident(name), column }) if name.starts_with('.') => {
+				let directive = (name.to_ascii_lowercase(), *column);
+				self.pos += 1;
+				Some(directive)
+			},
+			_ => None,
+		}
+	}
+
+	/// Name of a symbol being defined and its column
+	pub fn symbol_name(&mut self) -> Result<(String, usize), ParseError> {
+		self.ident("symbol name")
+	}
+
+	pub fn string(&mut self) -> Result<Vec<u8>, ParseError> {
+		let lexeme = self.next()?;
+		match lexeme.token {
+			Token::Str(bytes) => Ok(bytes),
+			_ => Err(ParseError::new(ParseErrorKind::Expected("string"), lexeme.column)),
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.pos >= self.tokens.len()
+	}
+
+	pub fn finish(&self) -> Result<(), ParseError> {
+		if self.is_empty() {
+			Ok(())
+		} else {
+			Err(ParseError::new(ParseErrorKind::TrailingInput, self.column()))
+		}
+	}
+
+	fn ident(&mut self, what: &'static str) -> Result<(String, usize), ParseError> {
+		let lexeme = self.next()?;
+		match lexeme.token {
+			Token::Ident(name) => Ok((name, lexeme.column)),
+			_ => Err(ParseError::new(ParseErrorKind::Expected(what), lexeme.column)),
+		}
+	}
+
+	fn peek_register(&self) -> Option<Register> {
+		match self.peek() {
+			Some(Token::Ident(name)) => Register::from_name(&name.to_ascii_lowercase()),
+			_ => None,
+		}
+	}
+
+	fn register(&mut self) -> Result<Register, ParseError> {
+		let (name, column) = self.ident("register")?;
+		Register::from_name(&name.to_ascii_lowercase())
+			.ok_or(ParseError::new(ParseErrorKind::UnknownRegister(name), column))
+	}
+
+	/// Value of a term and the number of addresses added into it, labels and `.` are addresses
+	fn term(&mut self) -> Result<(i64, i64), ParseError> {
+		let lexeme = self.next()?;
+		match lexeme.token {
+			Token::Number(value) => Ok((value, 0)),
+			Token::Minus => {
+				let (value, addresses) = self.term()?;
+				let value = value.checked_neg().ok_or(ParseError::new(ParseErrorKind::Overflow, lexeme.column))?;
+				Ok((value, -addresses))
+			},
+			// The location counter, the address of the current line
+			Token::Ident(name) if name == "." => Ok((self.pc as i64, 1)),
+			Token::Ident(name) => match (self.symbols)(&name) {
+				Some(Symbol::Label(addr)) => Ok((addr as i64, 1)),
+				Some(Symbol::Constant(value)) => Ok((value, 0)),
+				None => Err(ParseError::new(ParseErrorKind::UnknownSymbol(name), lexeme.column)),
+			},
+			_ => Err(ParseError::new(ParseErrorKind::Expected("expression"), lexeme.column)),
+		}
+	}
+
+	/// Sum of terms, along with the number of addresses in it
+	fn sum(&mut self) -> Result<(i64, i64), ParseError> {
+		let (mut value, mut addresses) = self.term()?;
+		loop {
+			let column = self.column();
+			let result = if self.eat(&Token::Plus) {
+				let (rhs, rhs_addresses) = self.term()?;
+				addresses += rhs_addresses;
+				value.checked_add(rhs)
+			} else if self.eat(&Token::Minus) {
+				let (rhs, rhs_addresses) = self.term()?;
+				addresses -= rhs_addresses;
+				value.checked_sub(rhs)
+			} else {
+				return Ok((value, addresses));
+			};
+			value = result.ok_or(ParseError::new(ParseErrorKind::Overflow, column))?;
+		}
+	}
+
+	/// Parses a sum of numbers and symbols
+	pub fn expr(&mut self) -> Result<i64, ParseError> {
+		self.sum().map(|(value, _)| value)
+	}
+
+	fn check_range(value: i64, min: i64, max: i64, column: usize) -> Result<i64, ParseError> {
+		if value < min || value > max {
+			Err(ParseError::new(ParseErrorKind::OutOfRange { value, min, max }, column))
+		} else {
+			Ok(value)
+		}
+	}
+
+	pub fn ranged_expr(&mut self, min: i64, max: i64) -> Result<i64, ParseError> {
+		let column = self.column();
+		let value = self.expr()?;
+		Self::check_range(value, min, max, column)
+	}
+
+	/// Expression that's either an address or a constant, the latter includes differences of addresses
+	///
+	/// Returns whether the value is an address, which moves with the program when it's linked.
+	pub fn address_expr(&mut self, min: i64, max: i64) -> Result<(i64, bool), ParseError> {
+		let column = self.column();
+		let (value, addresses) = self.sum()?;
+		let value = Self::check_range(value, min, max, column)?;
+		match addresses {
+			0 => Ok((value, false)),
+			1 => Ok((value, true)),
+			_ => Err(ParseError::new(ParseErrorKind::NotRelocatable, column)),
+		}
+	}
+
+	fn shift(&mut self) -> Result<Shift, ParseError> {
+		let kind = match self.peek() {
+			Some(Token::ShiftLeft) => Some(ShiftKind::Shl),
+			Some(Token::ShiftRight) => Some(ShiftKind::Shr),
+			Some(Token::Ident(name)) => shift_kind_from_str(&name.to_ascii_lowercase()),
+			_ => None,
+		};
+
+		if let Some(kind) = kind {
+			self.pos += 1;
+			let shift = self.ranged_expr(0, SHIFT_MAX)? as u8;
+			Ok(Shift {
+				kind,
+				shift,
+			})
+		} else {
+			Ok(Shift::default())
+		}
+	}
+
+	fn alu(&mut self, op: BinOp, cond: Option<Condition>, cond_column: usize) -> Result<Instruction, ParseError> {
+		let dest = self.register()?;
+		self.expect(Token::Comma, "','")?;
+		let lhs = self.register()?;
+		self.expect(Token::Comma, "','")?;
+
+		if let Some(rhs) = self.peek_register() {
+			if cond.is_some() {
+				return Err(ParseError::new(ParseErrorKind::UnexpectedCondition, cond_column));
+			}
+
+			self.pos += 1;
+			Ok(Instruction::Rrr(rrr::Instruction {
+				op,
+				dest,
+				lhs,
+				rhs,
+				shift: self.shift()?,
+			}))
+		} else {
+			Ok(Instruction::Rri(rri::Instruction {
+				op,
+				cond: cond.unwrap_or(Condition::Always),
+				dest,
+				src: lhs,
+				imm: self.ranged_expr(RRI_IMM_MIN, RRI_IMM_MAX)? as i16,
+			}))
+		}
+	}
+
+	fn memory(&mut self, op: LoadStoreOp) -> Result<Instruction, ParseError> {
+		let rd = self.register()?;
+		self.expect(Token::Comma, "','")?;
+		self.expect(Token::LBracket, "'['")?;
+		let rs = self.register()?;
+
+		let instruction = if self.eat(&Token::Plus) {
+			if let Some(rq) = self.peek_register() {
+				self.pos += 1;
+				memory::Instruction::Rr(memory::rr::Instruction {
+					op,
+					rd,
+					rs,
+					rq,
+					shift: self.shift()?,
+				})
+			} else {
+				let imm = self.ranged_expr(MEM_IMM_MIN, MEM_IMM_MAX)? as i16;
+				memory::Instruction::Ri(memory::ri::Instruction { op, rd, rs, imm })
+			}
+		} else if self.eat(&Token::Minus) {
+			let imm = -self.ranged_expr(-MEM_IMM_MAX, -MEM_IMM_MIN)? as i16;
+			memory::Instruction::Ri(memory::ri::Instruction { op, rd, rs, imm })
+		} else {
+			memory::Instruction::Ri(memory::ri::Instruction { op, rd, rs, imm: 0 })
+		};
+
+		self.expect(Token::RBracket, "']'")?;
+		Ok(Instruction::Memory(instruction))
+	}
+
+	/// Looks up a named CSR operand, names containing a `.` must be CSRs while others may also be symbols
+	fn csr_name(&mut self, width: Width) -> Result<Option<u32>, ParseError> {
+		let (Some(registry), Some(Lexeme { token: Token::Ident(name), column })) = (self.registry, self.tokens.get(self.pos)) else {
+			return Ok(None);
+		};
+
+		let (name, column) = (name.clone(), *column);
+		let Some(reg) = registry.lookup(&name) else {
+			return if name.contains('.') {
+				Err(ParseError::new(ParseErrorKind::UnknownCsr(name), column))
+			} else {
+				Ok(None)
+			};
+		};
+
+		if reg.register.width != width {
+			return Err(ParseError::new(ParseErrorKind::CsrWidthMismatch {
+				name,
+				expected: reg.register.width,
+				found: width,
+			}, column));
+		}
+
+		self.pos += 1;
+		Ok(Some(reg.address()))
+	}
+
+	fn csr(&mut self, op: LoadStoreOp) -> Result<Instruction, ParseError> {
+		let reg = self.register()?;
+		self.expect(Token::Comma, "','")?;
+		let imm = match self.csr_name(op.width)? {
+			Some(addr) => addr,
+			None => self.ranged_expr(0, CSR_IMM_MAX)? as u32,
+		};
+
+		Ok(Instruction::Csr(csr::Instruction {
+			op,
+			reg,
+			imm,
+		}))
+	}
+
+	/// Jump operand, which is always the target address as in GNU as, `.+8` jumps relative to the
+	/// instruction. Addresses wrap like the PC so every target is reachable.
+	///
+	/// Returns the target and whether it's a constant, jumps to those have to be relocated.
+	fn jump_target(&mut self) -> Result<(u32, bool), ParseError> {
+		let column = self.column();
+		let (value, is_address) = self.address_expr(i32::MIN as i64, u32::MAX as i64)?;
+		let target = value as u32;
+		let imm = target.wrapping_sub(self.pc) as i32;
+		if imm % 4 != 0 {
+			return Err(ParseError::new(ParseErrorKind::Misaligned(imm as i64), column));
+		}
+		Ok((target, !is_address))
+	}
+
+	fn jump(&mut self) -> Result<jump::Instruction, ParseError> {
+		let (target, fixed) = self.jump_target()?;
+		if fixed {
+			self.relocate(self.pc, RelocationKind::Jump);
+		}
+		Ok(jump::Instruction {
+			imm: target.wrapping_sub(self.pc) as i32,
+		})
+	}
+
+	/// `call target`, the operand is the same as for `j` with `.` referring to the call
+	fn call(&mut self) -> Result<Vec<Instruction>, ParseError> {
+		let (target, fixed) = self.jump_target()?;
+		if fixed {
+			self.relocate(self.pc.wrapping_add(4), RelocationKind::Jump);
+		}
+		// The jump's offset is 4 less than the one already checked for alignment
+		Ok(jump::call(self.pc, target).unwrap().to_vec())
+	}
+
+	/// `jalr rd, rs, imm`
+	fn jalr(&mut self) -> Result<Vec<Instruction>, ParseError> {
+		let rd_column = self.column();
+		let rd = self.register()?;
+		self.expect(Token::Comma, "','")?;
+		let rs = self.register()?;
+		self.expect(Token::Comma, "','")?;
+		let imm = self.ranged_expr(RRI_IMM_MIN, RRI_IMM_MAX)? as i16;
+
+		if rd == Register::pc() {
+			return Err(ParseError::new(ParseErrorKind::LinkToPc, rd_column));
+		}
+		if rd == rs && rd != Register::z() {
+			return Err(ParseError::new(ParseErrorKind::LinkOverwritesTarget(rd), rd_column));
+		}
+		Ok(super::jalr(rd, rs, imm).to_vec())
+	}
+
+	/// Lowercase mnemonic at the current position
+	fn mnemonic(&self) -> Option<String> {
+		match self.peek() {
+			Some(Token::Ident(name)) => Some(name.to_ascii_lowercase()),
+			_ => None,
+		}
+	}
+
+	/// Number of instructions the mnemonic at the current position expands to
+	pub fn instruction_count(&self) -> u32 {
+		match self.mnemonic().as_deref() {
+			Some("call" | "jalr") => 2,
+			_ => 1,
+		}
+	}
+
+	/// Parses an instruction or a pseudo instruction expanding to several, see `instruction_count`
+	pub fn instructions(&mut self) -> Result<Vec<Instruction>, ParseError> {
+		match self.mnemonic().as_deref() {
+			Some("call") => {
+				self.pos += 1;
+				self.call()
+			},
+			Some("jalr") => {
+				self.pos += 1;
+				self.jalr()
+			},
+			_ => self.instruction().map(|instruction| vec![instruction]),
+		}
+	}
+
+	/// Parses a single instruction, leaving any trailing tokens for the caller to check
+	pub fn instruction(&mut self) -> Result<Instruction, ParseError> {
+		let (mnemonic, column) = self.ident("mnemonic")?;
+		let mnemonic = mnemonic.to_ascii_lowercase();
+
+		// Column of each dot separated part of the mnemonic for error reporting
+		let mut parts = Vec::new();
+		let mut part_column = column;
+		for part in mnemonic.split('.') {
+			parts.push((part, part_column));
+			part_column += part.len() + 1;
+		}
+
+		let width = |index: usize| -> Result<Option<Width>, ParseError> {
+			parts.get(index).map(|(part, column)| {
+				width_from_str(part).ok_or(ParseError::new(ParseErrorKind::UnknownWidth(part.to_string()), *column))
+			}).transpose()
+		};
+
+		let load_store = |part: &str| match part {
+			"ld" => Some(LoadStore::Load),
+			"st" => Some(LoadStore::Store),
+			_ => None,
+		};
+
+		let unknown = ParseError::new(ParseErrorKind::UnknownMnemonic(mnemonic.clone()), column);
+		match parts[0].0 {
+			// Raw encodings are how words without a mnemonic, such as reserved kinds, are printed
+			"" if mnemonic == ".word" => {
+				let column = self.column();
+				let value = self.ranged_expr(0, u32::MAX as i64)? as u32;
+				Instruction::decode(value)
+					.map_err(|err| ParseError::new(ParseErrorKind::InvalidEncoding(err), column))
+			},
+			"j" if parts.len() == 1 => self.jump().map(Instruction::Jump),
+			"jr" if parts.len() == 1 => self.register().map(super::jr),
+			"ret" if parts.len() == 1 => Ok(super::ret()),
+			"csr" if parts.len() <= 3 => {
+				let op = parts.get(1).and_then(|(part, _)| load_store(part)).ok_or(unknown)?;
+				let width = width(2)?.unwrap_or(Width::Word);
+				self.csr(LoadStoreOp { op, width })
+			},
+			"ld" | "st" if parts.len() <= 2 => {
+				let op = load_store(parts[0].0).unwrap();
+				let width = width(1)?.ok_or(ParseError::new(ParseErrorKind::Expected("width suffix"), part_column - 1))?;
+				self.memory(LoadStoreOp { op, width })
+			},
+			name if parts.len() <= 2 => {
+				let op = binop_from_str(name).ok_or(unknown)?;
+				let cond = parts.get(1).map(|(part, column)| {
+					condition_from_str(part).ok_or(ParseError::new(ParseErrorKind::UnknownCondition(part.to_string()), *column))
+				}).transpose()?;
+				let cond_column = parts.get(1).map_or(column, |(_, column)| *column);
+				self.alu(op, cond, cond_column)
+			},
+			_ => Err(unknown),
+		}
+	}
+}
+
+/// Parses a single line of assembly into an instruction
+pub fn parse_line(line: &str) -> Result<Instruction, ParseError> {
+	let no_symbols = |_: &str| None;
+	let mut parser = Parser::new(line, 1, &no_symbols)?;
+	let instruction = parser.instruction()?;
+	parser.finish()?;
+	Ok(instruction)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn round_trip(text: &str) {
+		let instruction = parse_line(text).unwrap();
+		assert_eq!(instruction.to_string(), text);
+	}
+
+	#[test]
+	fn round_trip_alu() {
+		for op in (0..32).filter_map(BinOp::from_u32) {
+			round_trip(&format!("{op} r3, r4, r5"));
+			round_trip(&format!("{op} r3, r4, -12"));
+			for cond in (1..8).filter_map(Condition::from_u32) {
+				round_trip(&format!("{op}.{cond} r31, r0, 2047"));
+			}
+		}
+
+		for kind in (0..8).filter_map(ShiftKind::from_u32) {
+			round_trip(&format!("add r1, r2, r3 {kind} 31"));
+		}
+		round_trip("sub r1, r2, r3 ror 0");
+	}
+
+	#[test]
+	fn round_trip_memory() {
+		for op in ["ld", "st"] {
+			for width in ["b", "s", "w"] {
+				round_trip(&format!("{op}.{width} r1, [r2 + r3 << 2]"));
+				round_trip(&format!("{op}.{width} r1, [r2 + r3 asr 5]"));
+				round_trip(&format!("{op}.{width} r1, [r2 + r3]"));
+				round_trip(&format!("{op}.{width} r1, [r2 + 12]"));
+				round_trip(&format!("{op}.{width} r1, [r2 - 4096]"));
+				round_trip(&format!("{op}.{width} r1, [r2 + 4095]"));
+				round_trip(&format!("{op}.{width} r1, [r2]"));
+			}
+		}
+	}
+
+	#[test]
+	fn round_trip_csr_jump() {
+		round_trip("csr.st r5, 0x140");
+		round_trip("csr.ld r5, 0x3ffff");
+		round_trip("csr.ld.b r5, 0x141");
+		round_trip("csr.st.s r5, 0x0");
+		round_trip("j .+16");
+		round_trip("j .-8");
+		round_trip("j .+0");
+	}
+
+	#[test]
+	fn aliases() {
+		let i = parse_line("add sp, lr, a0").unwrap();
+		assert_eq!(i.to_string(), "add r28, r30, r1");
+
+		let i = parse_line("ST.W t3, [fp + o2 << 1]").unwrap();
+		assert_eq!(i.to_string(), "st.w r23, [r29 + r12 << 1]");
+
+		let i = parse_line("add.al r1, r2, 0x10 ; comment").unwrap();
+		assert_eq!(i.to_string(), "add r1, r2, 16");
+
+		let i = parse_line("csr.ld.w r1, 64").unwrap();
+		assert_eq!(i.to_string(), "csr.ld r1, 0x40");
+	}
+
+	#[test]
+	fn errors() {
+		let err = |text: &str| parse_line(text).unwrap_err();
+
+		assert_eq!(err("foo r1, r2, r3"), ParseError::new(ParseErrorKind::UnknownMnemonic("foo".into()), 1));
+		assert_eq!(err("add r1, r2, q3"), ParseError::new(ParseErrorKind::UnknownSymbol("q3".into()), 13));
+		assert_eq!(err("add r1, x2, r3"), ParseError::new(ParseErrorKind::UnknownRegister("x2".into()), 9));
+		assert_eq!(err("add.xx r1, r2, 3"), ParseError::new(ParseErrorKind::UnknownCondition("xx".into()), 5));
+		assert_eq!(err("add.nz r1, r2, r3"), ParseError::new(ParseErrorKind::UnexpectedCondition, 5));
+		assert_eq!(err("ld.q r1, [r2]"), ParseError::new(ParseErrorKind::UnknownWidth("q".into()), 4));
+		assert_eq!(err("ld r1, [r2]"), ParseError::new(ParseErrorKind::Expected("width suffix"), 3));
+		assert_eq!(err("add r1, r2, 2048"), ParseError::new(
+			ParseErrorKind::OutOfRange { value: 2048, min: -2048, max: 2047 }, 13));
+		assert_eq!(err("add r1, r2, r3 << 32"), ParseError::new(
+			ParseErrorKind::OutOfRange { value: 32, min: 0, max: 31 }, 19));
+		assert_eq!(err("j 6"), ParseError::new(ParseErrorKind::Misaligned(6), 3));
+		assert_eq!(err("ld.w r1, [r2"), ParseError::new(ParseErrorKind::UnexpectedEnd, 13));
+		assert_eq!(err("add r1, r2, 3 4"), ParseError::new(ParseErrorKind::TrailingInput, 15));
+		assert_eq!(err("add r1, r2, $"), ParseError::new(ParseErrorKind::UnexpectedCharacter('$'), 13));
+		assert_eq!(err("add r1, r2, 0xzz"), ParseError::new(ParseErrorKind::InvalidNumber("0xzz".into()), 13));
+		assert_eq!(err("add r1, r0, 0x7fffffffffffffff + 1"), ParseError::new(ParseErrorKind::Overflow, 32));
+		assert_eq!(err("add r1, r0, -0x7fffffffffffffff - 2"), ParseError::new(ParseErrorKind::Overflow, 33));
+	}
+}
@@ -0,0 +1,224 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+//! Assembles a source file into a raw binary, Intel HEX or ELF object
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use bibe_instr::{
+	asm::{AssembleError, Assembler, Program},
+	csr::registry::{Registry, RegistryParser},
+	image::Image,
+};
+use clap::{Parser, ValueEnum};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+	/// Program bytes starting at the origin
+	Binary,
+	Ihex,
+	/// Relocatable object with the code in `.text`, labels named by `.global` are exported
+	Elf,
+}
+
+impl OutputFormat {
+	/// Format implied by the output file's extension
+	fn from_path(path: &Path) -> Option<OutputFormat> {
+		match path.extension()?.to_str()? {
+			"bin" | "raw" => Some(OutputFormat::Binary),
+			"hex" | "ihex" => Some(OutputFormat::Ihex),
+			"o" | "elf" => Some(OutputFormat::Elf),
+			_ => None,
+		}
+	}
+
+	fn extension(self) -> &'static str {
+		match self {
+			OutputFormat::Binary => "bin",
+			OutputFormat::Ihex => "hex",
+			OutputFormat::Elf => "o",
+		}
+	}
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "bibe-as", version, about = "Assembles BIBE source files")]
+struct Args {
+	file: PathBuf,
+	/// Defaults to the input file with the output format's extension
+	#[arg(short, long)]
+	output: Option<PathBuf>,
+	/// Defaults to the one implied by the output file's extension, or ELF
+	#[arg(short, long, value_enum)]
+	format: Option<OutputFormat>,
+	/// Write a listing of each source line with its address and encoding
+	#[arg(short, long)]
+	listing: Option<PathBuf>,
+	/// CSR registry XML used to resolve CSR names instead of the built-in one
+	#[arg(long)]
+	registry: Option<PathBuf>,
+}
+
+/// `file:line:col: error: message` followed by the offending line and a caret under the column
+fn diagnostic(file: &str, source: &str, error: &AssembleError) -> String {
+	let mut out = format!("{file}:{}:{}: error: {}\n", error.line, error.column, error.kind);
+	if let Some(text) = source.lines().nth(error.line - 1) {
+		// Keep tabs so the caret lines up with the text above it
+		let indent: String = text.chars()
+			.take(error.column - 1)
+			.map(|c| if c == '\t' { '\t' } else { ' ' })
+			.collect();
+		writeln!(out, " {text}").unwrap();
+		writeln!(out, " {indent}^").unwrap();
+	}
+	out
+}
+
+/// Line number, address, encoded words and source text for each line
+///
+/// Lines emitting more than a word continue on the following lines, a trailing partial word is shown as bytes.
+fn listing(source: &str, program: &Program) -> String {
+	let bytes = program.bytes();
+	let mut out = String::new();
+	for (index, (text, placement)) in source.lines().zip(&program.lines).enumerate() {
+		// Lines before an `.org` can be below the origin, but never emit anything
+		let start = placement.addr.wrapping_sub(program.origin) as usize;
+		let emitted = match placement.size {
+			0 => &[][..],
+			size => &bytes[start..start + size as usize],
+		};
+		let mut chunks = emitted.chunks(4).enumerate().map(|(i, chunk)| {
+			let addr = placement.addr.wrapping_add(4 * i as u32);
+			let value = match chunk.try_into() {
+				Ok(word) => format!("{:08x}", u32::from_le_bytes(word)),
+				Err(_) => chunk.iter().map(|b| format!("{b:02x}")).collect(),
+			};
+			format!("{addr:08x} {value:8}")
+		});
+
+		let first = chunks.next().unwrap_or_else(|| format!("{:08x} {:8}", placement.addr, ""));
+		writeln!(out, "{:5} {first}\t{text}", index + 1).unwrap();
+		for chunk in chunks {
+			writeln!(out, "{:5} {chunk}", "").unwrap();
+		}
+	}
+	out
+}
+
+fn load_registry(path: Option<&Path>) -> Result<Registry, String> {
+	let Some(path) = path else {
+		return Ok(Registry::builtin());
+	};
+
+	let mut parser = RegistryParser::new();
+	parser.load_file(path).map_err(|err| err.to_string())?;
+	parser.finish().map_err(|err| err.to_string())
+}
+
+enum Failure {
+	/// Source errors, already formatted as diagnostics
+	Assemble(String),
+	Other(String),
+}
+
+impl From<String> for Failure {
+	fn from(message: String) -> Self {
+		Failure::Other(message)
+	}
+}
+
+fn run(args: &Args) -> Result<(), Failure> {
+	let path = args.file.display();
+	let source = std::fs::read_to_string(&args.file).map_err(|err| format!("{path}: {err}"))?;
+	let registry = load_registry(args.registry.as_deref())?;
+
+	let program = Assembler::new().with_registry(registry).assemble(&source).map_err(|errors| {
+		let file = path.to_string();
+		Failure::Assemble(errors.iter().map(|e| diagnostic(&file, &source, e)).collect())
+	})?;
+
+	let format = args.format
+		.or_else(|| args.output.as_deref().and_then(OutputFormat::from_path))
+		.unwrap_or(OutputFormat::Elf);
+	let output = args.output.clone().unwrap_or_else(|| args.file.with_extension(format.extension()));
+
+	let image = Image::from(&program);
+	let bytes = match format {
+		OutputFormat::Binary => image.to_raw(),
+		OutputFormat::Ihex => image.to_intel_hex().into_bytes(),
+		OutputFormat::Elf => image.to_elf(),
+	};
+	std::fs::write(&output, bytes).map_err(|err| format!("{}: {err}", output.display()))?;
+
+	if let Some(path) = &args.listing {
+		std::fs::write(path, listing(&source, &program)).map_err(|err| format!("{}: {err}", path.display()))?;
+	}
+	Ok(())
+}
+
+fn main() -> ExitCode {
+	let args = Args::parse();
+	match run(&args) {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(Failure::Assemble(diagnostics)) => {
+			eprint!("{diagnostics}");
+			ExitCode::FAILURE
+		},
+		Err(Failure::Other(err)) => {
+			eprintln!("bibe-as: {err}");
+			ExitCode::FAILURE
+		},
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn listed() {
+		let source = "\
+start:
+	.org 0x100
+	add r1, r0, 7
+	.word 1, 2
+	.byte 3
+	.align 4
+	j start";
+		let program = Assembler::new().assemble(source).unwrap();
+
+		// Starts on a new line to keep the first line's padding
+		let expected = "
+    1 00000000         \tstart:
+    2 00000000         \t\t.org 0x100
+    3 00000100 40100007\t\tadd r1, r0, 7
+    4 00000104 00000001\t\t.word 1, 2
+      00000108 00000002
+    5 0000010c 03      \t\t.byte 3
+    6 0000010d         \t\t.align 4
+    7 00000110 ffffffbc\t\tj start
+";
+		assert_eq!(listing(source, &program), expected[1..]);
+	}
+
+	#[test]
+	fn diagnostics() {
+		let source = "\tadd r1, r0, 7\n\tadd r1, r0,\tfoo\n";
+		let errors = Assembler::new().assemble(source).unwrap_err();
+
+		assert_eq!(diagnostic("test.s", source, &errors[0]), format!("\
+test.s:2:14: error: {}
+ \tadd r1, r0,\tfoo
+ \t           \t^
+", errors[0].kind));
+	}
+
+	#[test]
+	fn formats() {
+		assert_eq!(OutputFormat::from_path(Path::new("a.hex")), Some(OutputFormat::Ihex));
+		assert_eq!(OutputFormat::from_path(Path::new("a.bin")), Some(OutputFormat::Binary));
+		assert_eq!(OutputFormat::from_path(Path::new("a.o")), Some(OutputFormat::Elf));
+		assert_eq!(OutputFormat::from_path(Path::new("a.elf")), Some(OutputFormat::Elf));
+		assert_eq!(OutputFormat::Elf.extension(), "o");
+		assert_eq!(OutputFormat::from_path(Path::new("a.out")), None);
+	}
+}
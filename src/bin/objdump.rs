@@ -0,0 +1,305 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+//! Prints an annotated disassembly of a raw, Intel HEX or ELF program image
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use bibe_instr::{
+	Endian,
+	decode_stream,
+	csr::registry::{Registry, RegistryParser},
+	image::{Format, Image},
+};
+use clap::{Parser, ValueEnum};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum InputFormat {
+	/// Detected from the contents
+	Auto,
+	Raw,
+	Ihex,
+	Elf,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ByteOrder {
+	Little,
+	Big,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum RegisterNames {
+	/// `r0` to `r31`
+	Numeric,
+	/// Calling convention names such as `sp`, `a0` and `lr`
+	Abi,
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "bibe-objdump", version, about = "Disassembles BIBE program images")]
+struct Args {
+	file: PathBuf,
+	#[arg(short, long, value_enum, default_value_t = InputFormat::Auto)]
+	format: InputFormat,
+	/// Load address of a raw image
+	#[arg(long, value_parser = parse_addr, default_value = "0")]
+	base: u32,
+	/// Don't list words before this address
+	#[arg(long, value_parser = parse_addr)]
+	start_address: Option<u32>,
+	/// Don't list words at or after this address
+	#[arg(long, value_parser = parse_addr)]
+	stop_address: Option<u32>,
+	/// Byte order of instruction words, defaults to the one recorded in ELF files or little endian
+	#[arg(short = 'E', long, value_enum)]
+	endian: Option<ByteOrder>,
+	#[arg(short = 'M', long, value_enum, default_value_t = RegisterNames::Numeric)]
+	registers: RegisterNames,
+	/// CSR registry XML used to name CSR operands instead of the built-in one
+	#[arg(long)]
+	registry: Option<PathBuf>,
+}
+
+fn parse_addr(text: &str) -> Result<u32, String> {
+	let parsed = match text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+		Some(hex) => u32::from_str_radix(hex, 16),
+		None => text.parse(),
+	};
+	parsed.map_err(|err| format!("invalid address '{text}': {err}"))
+}
+
+struct Options {
+	endian: Endian,
+	abi: bool,
+	start: u32,
+	stop: Option<u32>,
+}
+
+impl Options {
+	fn contains(&self, addr: u32) -> bool {
+		addr >= self.start && self.stop.is_none_or(|stop| addr < stop)
+	}
+}
+
+/// `<name>` or `<name+0x8>` for the closest symbol at or below `addr`
+fn symbolize(symbols: &BTreeMap<u32, String>, addr: u32) -> Option<String> {
+	let (&base, name) = symbols.range(..=addr).next_back()?;
+	Some(match addr - base {
+		0 => format!("<{name}>"),
+		offset => format!("<{name}+{offset:#x}>"),
+	})
+}
+
+fn listing(image: &Image, registry: &Registry, options: &Options) -> String {
+	let mut out = String::new();
+	for segment in &image.segments {
+		let mut stream = decode_stream(&segment.bytes, options.endian, segment.addr);
+		let words: Vec<_> = stream.by_ref().filter(|(addr, _)| options.contains(*addr)).collect();
+		let tail_addr = segment.addr.wrapping_add(segment.bytes.len() as u32 & !3);
+		let tail = Some(stream.remainder()).filter(|tail| !tail.is_empty() && options.contains(tail_addr));
+		if words.is_empty() && tail.is_none() {
+			continue;
+		}
+
+		if !out.is_empty() {
+			writeln!(out).unwrap();
+		}
+		match &segment.name {
+			Some(name) => writeln!(out, "Disassembly of section {name}:").unwrap(),
+			None => writeln!(out, "Disassembly of {:#010x}:", segment.addr).unwrap(),
+		}
+
+		for (addr, result) in words {
+			if let Some(name) = image.symbols.get(&addr) {
+				writeln!(out).unwrap();
+				writeln!(out, "{addr:08x} <{name}>:").unwrap();
+			}
+
+			let offset = addr.wrapping_sub(segment.addr) as usize;
+			let value = options.endian.read_word(segment.bytes[offset..offset + 4].try_into().unwrap());
+			write!(out, "{addr:8x}:\t{value:08x}\t").unwrap();
+
+			let instruction = match result {
+				Ok(instruction) => instruction,
+				Err(err) => {
+					writeln!(out, ".word {value:#010x}\t; {err}").unwrap();
+					continue;
+				},
+			};
+
+			if options.abi {
+				write!(out, "{:#}", instruction.display_with(registry)).unwrap();
+			} else {
+				write!(out, "{}", instruction.display_with(registry)).unwrap();
+			}
+
+			if let Some(target) = instruction.branch_target(addr) {
+				write!(out, "\t; {target:#x}").unwrap();
+				if let Some(symbol) = symbolize(&image.symbols, target) {
+					write!(out, " {symbol}").unwrap();
+				}
+			} else if instruction.is_return() {
+				write!(out, "\t; return").unwrap();
+			}
+			writeln!(out).unwrap();
+		}
+
+		if let Some(tail) = tail {
+			let bytes: Vec<String> = tail.iter().map(|b| format!("{b:#04x}")).collect();
+			writeln!(out, "{tail_addr:8x}:\t{:8}\t.byte {}", "", bytes.join(", ")).unwrap();
+		}
+	}
+	out
+}
+
+fn load_registry(path: Option<&Path>) -> Result<Registry, String> {
+	let Some(path) = path else {
+		return Ok(Registry::builtin());
+	};
+
+	let mut parser = RegistryParser::new();
+	parser.load_file(path).map_err(|err| err.to_string())?;
+	parser.finish().map_err(|err| err.to_string())
+}
+
+fn run(args: &Args) -> Result<String, String> {
+	let path = args.file.display();
+	let bytes = std::fs::read(&args.file).map_err(|err| format!("{path}: {err}"))?;
+	let format = match args.format {
+		InputFormat::Auto => Format::detect(&bytes),
+		InputFormat::Raw => Format::Raw,
+		InputFormat::Ihex => Format::IntelHex,
+		InputFormat::Elf => Format::Elf,
+	};
+	let image = Image::read(&bytes, format, args.base).map_err(|err| format!("{path}: {err}"))?;
+	let registry = load_registry(args.registry.as_deref())?;
+
+	let endian = match args.endian {
+		Some(ByteOrder::Little) => Endian::Little,
+		Some(ByteOrder::Big) => Endian::Big,
+		None => image.endian.unwrap_or_default(),
+	};
+	let options = Options {
+		endian,
+		abi: args.registers == RegisterNames::Abi,
+		start: args.start_address.unwrap_or(0),
+		stop: args.stop_address,
+	};
+
+	let mut out = String::new();
+	if let Some(entry) = image.entry {
+		writeln!(out, "Entry point {entry:#010x}").unwrap();
+		writeln!(out).unwrap();
+	}
+	out.push_str(&listing(&image, &registry, &options));
+	Ok(out)
+}
+
+fn main() -> ExitCode {
+	let args = Args::parse();
+	match run(&args) {
+		Ok(listing) => {
+			print!("{listing}");
+			ExitCode::SUCCESS
+		},
+		Err(err) => {
+			eprintln!("bibe-objdump: {err}");
+			ExitCode::FAILURE
+		},
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use bibe_instr::asm::Assembler;
+
+	fn image(source: &str, endian: Endian) -> Image {
+		let program = Assembler::new().with_registry(Registry::builtin()).assemble(source).unwrap();
+		let mut bytes: Vec<u8> = program.words.iter().flat_map(|w| endian.write_word(*w)).collect();
+		bytes.extend_from_slice(b"hi");
+
+		let mut image = Image::read(&bytes, Format::Raw, program.origin).unwrap();
+		image.symbols = program.symbols.iter()
+			.filter_map(|(name, symbol)| match symbol {
+				bibe_instr::asm::Symbol::Label(addr) => Some((*addr, name.clone())),
+				_ => None,
+			})
+			.collect();
+		image
+	}
+
+	fn options(endian: Endian, abi: bool) -> Options {
+		Options {
+			endian,
+			abi,
+			start: 0,
+			stop: None,
+		}
+	}
+
+	const SOURCE: &str = "
+		.org 0x100
+	start:
+		add sp, sp, -16
+		csr.ld a0, isr.err1
+	loop:
+		add.z pc, pc, 4
+		j loop
+		.word 0x0f800000
+		jalr lr, a1, 0
+		ret
+	";
+
+	#[test]
+	fn annotated() {
+		let image = image(SOURCE, Endian::Little);
+		let listing = listing(&image, &Registry::builtin(), &options(Endian::Little, false));
+
+		assert_eq!(listing, "\
+Disassembly of 0x00000100:
+
+00000100 <start>:
+     100:\t41ce0ff0\tadd r28, r28, -16
+     104:\t19040044\tcsr.ld r1, isr.err1
+
+00000108 <loop>:
+     108:\t41ffb004\tadd.z r31, r31, 4\t; 0x110 <loop+0x8>
+     10c:\tffffffff\tj .-4\t; 0x108 <loop>
+     110:\t0f800000\t.word 0x0f800000\t; unknown operation 31 in 0x0f800000 bits [27:23]
+     114:\t41ef8004\tadd r30, r31, 4
+     118:\t41f10000\tadd r31, r2, 0
+     11c:\t41ff0000\tadd r31, r30, 0\t; return
+     120:\t        \t.byte 0x68, 0x69
+");
+	}
+
+	#[test]
+	fn options_apply() {
+		let image = image(SOURCE, Endian::Big);
+		let mut options = options(Endian::Big, true);
+		options.start = 0x108;
+		options.stop = Some(0x110);
+
+		assert_eq!(listing(&image, &Registry::builtin(), &options), "\
+Disassembly of 0x00000100:
+
+00000108 <loop>:
+     108:\t41ffb004\tadd.z pc, pc, 4\t; 0x110 <loop+0x8>
+     10c:\tffffffff\tj .-4\t; 0x108 <loop>
+");
+
+		options.start = 0x200;
+		options.stop = None;
+		assert_eq!(listing(&image, &Registry::builtin(), &options), "");
+	}
+
+	#[test]
+	fn addresses() {
+		assert_eq!(parse_addr("0x100"), Ok(0x100));
+		assert_eq!(parse_addr("256"), Ok(256));
+		assert!(parse_addr("0x1_0000_0000").is_err());
+		assert!(parse_addr("start").is_err());
+	}
+}
@@ -0,0 +1,294 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+use std::fmt::Write;
+
+use xmltree::{Element, EmitterConfig, XMLNode};
+
+use super::registry::{Access, Block, Field, Register, Registry};
+use super::regs::CSR_BLOCK_SIZE;
+
+fn width_name(reg: &Register) -> &'static str {
+	match reg.width.to_len() {
+		1 => "byte",
+		2 => "short",
+		_ => "word",
+	}
+}
+
+fn constant_name(block: &Block, reg: &Register) -> String {
+	format!("{}_{}", block.name, reg.name).to_uppercase()
+}
+
+fn bits(field: &Field) -> String {
+	if field.width == 1 {
+		format!("{}", field.lsb)
+	} else {
+		format!("{}:{}", field.lsb + field.width - 1, field.lsb)
+	}
+}
+
+fn element(name: &str, text: impl ToString) -> XMLNode {
+	let mut element = Element::new(name);
+	element.children.push(XMLNode::Text(text.to_string()));
+	XMLNode::Element(element)
+}
+
+fn svd_access(access: Access) -> &'static str {
+	match access {
+		Access::ReadWrite | Access::WriteOneToClear => "read-write",
+		Access::ReadOnly => "read-only",
+		Access::WriteOnly => "write-only",
+	}
+}
+
+fn svd_children(node: &mut Element, access: Access) {
+	node.children.push(element("access", svd_access(access)));
+	if access == Access::WriteOneToClear {
+		node.children.push(element("modifiedWriteValues", "oneToClear"));
+	}
+}
+
+impl Registry {
+	/// Rust module with the same constants as `csr::regs`
+	pub fn to_rust(&self) -> String {
+		let mut out = String::new();
+		writeln!(out, "pub const CSR_BLOCK_SIZE: u32 = {CSR_BLOCK_SIZE};").unwrap();
+
+		for block in self.blocks_in_order() {
+			let prefix = block.name.to_uppercase();
+			writeln!(out).unwrap();
+			writeln!(out, "pub const {prefix}_BASE: u32 = 0x{:03X};", block.base).unwrap();
+			if block.count == 1 {
+				writeln!(out, "pub const {prefix}_SIZE: u32 = CSR_BLOCK_SIZE;").unwrap();
+			} else {
+				writeln!(out, "pub const {prefix}_SIZE: u32 = {} * CSR_BLOCK_SIZE;", block.count).unwrap();
+			}
+
+			for reg in &block.registers {
+				let name = constant_name(block, reg);
+				writeln!(
+					out,
+					"/// `{}` register of the `{}` block, {} {}",
+					reg.name,
+					block.name,
+					width_name(reg),
+					reg.access.as_attribute(),
+				).unwrap();
+				writeln!(out, "pub const {name}_REG: u32 = 0x{:03X};", block.base + reg.offset).unwrap();
+				for alias in &reg.aliases {
+					writeln!(out, "pub const {}_REG: u32 = {name}_REG;", alias.to_uppercase()).unwrap();
+				}
+				for field in &reg.fields {
+					let field_name = field.name.to_uppercase();
+					writeln!(out, "pub const {name}_{field_name}_LSB: u32 = {};", field.lsb).unwrap();
+					writeln!(out, "pub const {name}_{field_name}_MASK: u32 = 0x{:X};", field.mask()).unwrap();
+				}
+			}
+		}
+
+		out
+	}
+
+	/// C header with a `#define` for every constant in `to_rust`
+	pub fn to_c_header(&self) -> String {
+		let mut out = String::new();
+		writeln!(out, "/* Generated from the BIBE CSR registry */").unwrap();
+		writeln!(out, "#ifndef BIBE_CSR_H").unwrap();
+		writeln!(out, "#define BIBE_CSR_H").unwrap();
+		writeln!(out).unwrap();
+		writeln!(out, "#define CSR_BLOCK_SIZE {CSR_BLOCK_SIZE}u").unwrap();
+
+		for block in self.blocks_in_order() {
+			let prefix = block.name.to_uppercase();
+			writeln!(out).unwrap();
+			writeln!(out, "#define {prefix}_BASE 0x{:03X}u", block.base).unwrap();
+			writeln!(out, "#define {prefix}_SIZE 0x{:03X}u", block.size()).unwrap();
+
+			for reg in &block.registers {
+				let name = constant_name(block, reg);
+				writeln!(out, "#define {name}_REG 0x{:03X}u", block.base + reg.offset).unwrap();
+				for alias in &reg.aliases {
+					writeln!(out, "#define {}_REG {name}_REG", alias.to_uppercase()).unwrap();
+				}
+				for field in &reg.fields {
+					let field_name = field.name.to_uppercase();
+					writeln!(out, "#define {name}_{field_name}_LSB {}u", field.lsb).unwrap();
+					writeln!(out, "#define {name}_{field_name}_MASK 0x{:X}u", field.mask()).unwrap();
+				}
+			}
+		}
+
+		writeln!(out).unwrap();
+		writeln!(out, "#endif").unwrap();
+		out
+	}
+
+	/// Markdown reference with a register table per block and a bitfield table per register with fields
+	pub fn to_markdown(&self) -> String {
+		let mut out = String::new();
+		writeln!(out, "# CSR map").unwrap();
+
+		for block in self.blocks_in_order() {
+			writeln!(out).unwrap();
+			writeln!(out, "## {}", block.name).unwrap();
+			writeln!(out).unwrap();
+			writeln!(out, "Base `0x{:03X}`, size `0x{:03X}`", block.base, block.size()).unwrap();
+			writeln!(out).unwrap();
+			writeln!(out, "| Address | Name | Width | Access | Reset | Aliases |").unwrap();
+			writeln!(out, "|---------|------|-------|--------|-------|---------|").unwrap();
+			for reg in &block.registers {
+				let aliases: Vec<String> = reg.aliases.iter().map(|a| format!("`{a}`")).collect();
+				writeln!(
+					out,
+					"| `0x{:03X}` | `{}_{}` | {} | {} | `0x{:X}` | {} |",
+					block.base + reg.offset,
+					block.name,
+					reg.name,
+					width_name(reg),
+					reg.access.as_attribute(),
+					reg.reset,
+					aliases.join(", "),
+				).unwrap();
+			}
+
+			for reg in block.registers.iter().filter(|r| !r.fields.is_empty()) {
+				writeln!(out).unwrap();
+				writeln!(out, "### {}_{}", block.name, reg.name).unwrap();
+				writeln!(out).unwrap();
+				writeln!(out, "| Bits | Field | Access | Reset |").unwrap();
+				writeln!(out, "|------|-------|--------|-------|").unwrap();
+				for field in &reg.fields {
+					writeln!(
+						out,
+						"| `{}` | `{}` | {} | `0x{:X}` |",
+						bits(field),
+						field.name,
+						field.access.as_attribute(),
+						field.reset,
+					).unwrap();
+				}
+			}
+		}
+
+		out
+	}
+
+	/// XML following the layout of a CMSIS-SVD device description, one peripheral per block
+	pub fn to_svd(&self, device: &str) -> String {
+		let mut root = Element::new("device");
+		root.children.push(element("name", device));
+		root.children.push(element("addressUnitBits", 8));
+		root.children.push(element("width", 32));
+
+		let mut peripherals = Element::new("peripherals");
+		for block in self.blocks_in_order() {
+			let mut peripheral = Element::new("peripheral");
+			peripheral.children.push(element("name", &block.name));
+			peripheral.children.push(element("baseAddress", format!("{:#010x}", block.base)));
+
+			let mut address_block = Element::new("addressBlock");
+			address_block.children.push(element("offset", "0x0"));
+			address_block.children.push(element("size", format!("{:#x}", block.size())));
+			address_block.children.push(element("usage", "registers"));
+			peripheral.children.push(XMLNode::Element(address_block));
+
+			let mut registers = Element::new("registers");
+			for reg in &block.registers {
+				let mut register = Element::new("register");
+				register.children.push(element("name", &reg.name));
+				if !reg.aliases.is_empty() {
+					register.children.push(element("description", format!("Aliases: {}", reg.aliases.join(", "))));
+				}
+				register.children.push(element("addressOffset", format!("{:#x}", reg.offset)));
+				register.children.push(element("size", reg.width.to_len() * 8));
+				svd_children(&mut register, reg.access);
+				register.children.push(element("resetValue", format!("{:#010x}", reg.reset)));
+
+				if !reg.fields.is_empty() {
+					let mut fields = Element::new("fields");
+					for field in &reg.fields {
+						let mut node = Element::new("field");
+						node.children.push(element("name", &field.name));
+						node.children.push(element("bitOffset", field.lsb));
+						node.children.push(element("bitWidth", field.width));
+						svd_children(&mut node, field.access);
+						fields.children.push(XMLNode::Element(node));
+					}
+					register.children.push(XMLNode::Element(fields));
+				}
+				registers.children.push(XMLNode::Element(register));
+			}
+			peripheral.children.push(XMLNode::Element(registers));
+			peripherals.children.push(XMLNode::Element(peripheral));
+		}
+		root.children.push(XMLNode::Element(peripherals));
+
+		let mut out = Vec::new();
+		let config = EmitterConfig::new().perform_indent(true).indent_string("\t");
+		// Writing to a Vec can't fail
+		root.write_with_config(&mut out, config).unwrap();
+		String::from_utf8(out).unwrap()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn rust() {
+		let rust = Registry::builtin().to_rust();
+
+		assert!(rust.starts_with("pub const CSR_BLOCK_SIZE: u32 = 64;\n\npub const PSR_BASE: u32 = 0x000;\n"));
+		assert!(rust.contains("pub const ISR_SIZE: u32 = 3 * CSR_BLOCK_SIZE;\n"));
+		assert!(rust.contains("pub const CSR_PSR_REG: u32 = PSR_PSR0_REG;\n"));
+		assert!(rust.contains("/// `char_in0` register of the `dbg_out` block, byte ro\npub const DBG_OUT_CHAR_IN0_REG: u32 = 0x141;\n"));
+		assert!(rust.contains("pub const PSR_PSR0_NEGATIVE_MASK: u32 = 0x8;\n"));
+	}
+
+	#[test]
+	fn c_header() {
+		let header = Registry::builtin().to_c_header();
+
+		assert!(header.contains("#ifndef BIBE_CSR_H\n#define BIBE_CSR_H\n"));
+		assert!(header.contains("#define DBG_OUT_BASE 0x100u\n#define DBG_OUT_SIZE 0x100u\n"));
+		assert!(header.contains("#define ISR_PC_REG 0x0F8u\n"));
+		assert!(header.contains("#define CSR_PSR_REG PSR_PSR0_REG\n"));
+		assert!(header.contains("#define PSR_PSR0_CARRY_LSB 1u\n"));
+		assert!(header.trim_end().ends_with("#endif"));
+	}
+
+	#[test]
+	fn markdown() {
+		let markdown = Registry::builtin().to_markdown();
+
+		assert!(markdown.contains("## isr\n\nBase `0x040`, size `0x0C0`\n"));
+		assert!(markdown.contains("| `0x000` | `psr_psr0` | word | rw | `0x0` | `csr_psr` |\n"));
+		assert!(markdown.contains("| `0x100` | `dbg_out_status` | word | ro | `0x1` |  |\n"));
+		assert!(markdown.contains("### psr_psr0\n"));
+		assert!(markdown.contains("| `1` | `char_in_ready` | ro | `0x0` |\n"));
+	}
+
+	#[test]
+	fn svd() {
+		let svd = Registry::builtin().to_svd("bibe");
+		let root = Element::parse(svd.as_bytes()).unwrap();
+
+		assert_eq!(root.get_child("name").unwrap().get_text().unwrap(), "bibe");
+		let peripherals: Vec<_> = root.get_child("peripherals").unwrap().children.iter()
+			.filter_map(|n| n.as_element())
+			.collect();
+		let names: Vec<_> = peripherals.iter()
+			.map(|p| p.get_child("name").unwrap().get_text().unwrap().into_owned())
+			.collect();
+		assert_eq!(names, ["psr", "isr", "dbg_out"]);
+
+		let dbg_out = peripherals[2];
+		assert_eq!(dbg_out.get_child("baseAddress").unwrap().get_text().unwrap(), "0x00000100");
+		let status = dbg_out.get_child("registers").unwrap().get_child("register").unwrap();
+		assert_eq!(status.get_child("access").unwrap().get_text().unwrap(), "read-only");
+		assert_eq!(status.get_child("resetValue").unwrap().get_text().unwrap(), "0x00000001");
+		let field = status.get_child("fields").unwrap().get_child("field").unwrap();
+		assert_eq!(field.get_child("name").unwrap().get_text().unwrap(), "char_out_ready");
+		assert_eq!(field.get_child("bitWidth").unwrap().get_text().unwrap(), "1");
+	}
+}
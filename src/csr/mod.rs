@@ -1,13 +1,22 @@
 /* Copyright 2023 Robert Zieba, see LICENSE file for full license. */
+use std::fmt;
+
 use bitfield::bitfield;
 
+pub mod export;
 pub mod regs;
 pub mod registry;
 
 use crate::{
-	Encode, Kind, LoadStoreOp, Register
+	DecodeError, DecodeErrorKind, Encode, EncodeError, Kind, LoadStore, LoadStoreOp, Register, Width,
+	error::check_unsigned,
 };
 
+use registry::{Access, Registry};
+
+/// Width of the CSR address field
+pub const IMM_BITS: u8 = 18;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Instruction {
 	pub op: LoadStoreOp,
@@ -25,11 +34,17 @@ bitfield! {
 }
 
 impl Encode for Instruction {
-	fn decode(value: u32) -> Option<Self> {
+	fn decode(value: u32) -> Result<Self, DecodeError> {
 		let bitfield = Bitfield(value);
-		Some(Instruction {
-			op: LoadStoreOp::decode(bitfield.op())?,
-			reg: Register::new(bitfield.reg() as u8)?,
+		let kind = Kind::decode(value)?;
+
+		if kind != Kind::Csr {
+			return Err(DecodeError::new(DecodeErrorKind::UnexpectedKind(kind), value, 31, 27));
+		}
+
+		Ok(Instruction {
+			op: LoadStoreOp::decode(bitfield.op()).map_err(|e| e.within(value, 23))?,
+			reg: Register::decode(bitfield.reg()).map_err(|e| e.within(value, 18))?,
 			imm: bitfield.imm(),
 		})
 	}
@@ -42,4 +57,207 @@ impl Encode for Instruction {
 
 		bitfield.0
 	}
-}
\ No newline at end of file
+
+	fn try_encode(&self) -> Result<u32, EncodeError> {
+		check_unsigned(self.imm as i64, IMM_BITS)?;
+		Ok(self.encode())
+	}
+}
+
+/// CSR that `reg` is banked into on ISR entry, `r0` is always zero so it has no slot
+pub fn isr_save_slot(reg: Register) -> Option<u32> {
+	match reg.as_u8() {
+		0 => None,
+		28 => Some(regs::ISR_SP_REG),
+		29 => Some(regs::ISR_FP_REG),
+		30 => Some(regs::ISR_LR_REG),
+		31 => Some(regs::ISR_PC_REG),
+		n => Some(regs::ISR_R1_REG + (n as u32 - 1) * 4),
+	}
+}
+
+/// Register saved in the ISR slot at `addr`, the inverse of `isr_save_slot`
+pub fn isr_slot_register(addr: u32) -> Option<Register> {
+	(1..32)
+		.filter_map(Register::new)
+		.find(|&reg| isr_save_slot(reg) == Some(addr))
+}
+
+/// CSR access that the target register doesn't allow
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AccessViolation {
+	StoreToReadOnly {
+		addr: u32,
+		name: String,
+	},
+	LoadFromWriteOnly {
+		addr: u32,
+		name: String,
+	},
+}
+
+impl fmt::Display for AccessViolation {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			AccessViolation::StoreToReadOnly { addr, name } => write!(f, "store to read only CSR {name} ({addr:#x})"),
+			AccessViolation::LoadFromWriteOnly { addr, name } => write!(f, "load from write only CSR {name} ({addr:#x})"),
+		}
+	}
+}
+
+impl std::error::Error for AccessViolation {}
+
+impl Instruction {
+	/// Checks the access against the permissions of every register in `registry` it touches,
+	/// addresses it doesn't describe are allowed. Violations are reported at the register's address.
+	pub fn check_access(&self, registry: &Registry) -> Result<(), AccessViolation> {
+		for reg in registry.registers_in(self.imm, self.op.width.to_len()) {
+			let (addr, name) = (reg.address(), || reg.qualified_name());
+			match (self.op.op, reg.register.access) {
+				(LoadStore::Store, Access::ReadOnly) => return Err(AccessViolation::StoreToReadOnly { addr, name: name() }),
+				(LoadStore::Load, Access::WriteOnly) => return Err(AccessViolation::LoadFromWriteOnly { addr, name: name() }),
+				_ => {},
+			}
+		}
+		Ok(())
+	}
+}
+
+impl Instruction {
+	/// Displays the address as `block.reg` when `registry` has a register starting there
+	pub fn display_with<'a>(&'a self, registry: &'a Registry) -> Symbolic<'a, Self> {
+		Symbolic::new(self, registry)
+	}
+
+	fn fmt_mnemonic(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "csr.{}", self.op.op)?;
+		// Word access is the common case for CSRs, only narrower accesses are spelled out
+		if self.op.width != Width::Word {
+			write!(f, ".{}", self.op.width)?;
+		}
+		write!(f, " {}, ", self.reg.named(f.alternate()))
+	}
+}
+
+impl fmt::Display for Instruction {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		self.fmt_mnemonic(f)?;
+		write!(f, "{:#x}", self.imm)
+	}
+}
+
+/// Instruction displayed with symbolic CSR names, see `Instruction::display_with`
+pub struct Symbolic<'a, T> {
+	instruction: &'a T,
+	registry: &'a Registry,
+}
+
+impl<'a, T> Symbolic<'a, T> {
+	pub(crate) fn new(instruction: &'a T, registry: &'a Registry) -> Self {
+		Symbolic {
+			instruction,
+			registry,
+		}
+	}
+}
+
+impl fmt::Display for Symbolic<'_, Instruction> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.registry.register_at(self.instruction.imm) {
+			Some(reg) if reg.address() == self.instruction.imm => {
+				self.instruction.fmt_mnemonic(f)?;
+				write!(f, "{}.{}", reg.block.name, reg.register.name)
+			},
+			_ => self.instruction.fmt(f),
+		}
+	}
+}
+
+impl fmt::Display for Symbolic<'_, crate::Instruction> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.instruction {
+			crate::Instruction::Csr(i) => i.display_with(self.registry).fmt(f),
+			i => i.fmt(f),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use regs::*;
+
+	fn csr(op: LoadStore, imm: u32) -> Instruction {
+		Instruction {
+			op: LoadStoreOp { op, width: Width::Word },
+			reg: Register::r1(),
+			imm,
+		}
+	}
+
+	#[test]
+	fn check_access() {
+		let registry = Registry::builtin();
+
+		assert_eq!(csr(LoadStore::Load, DBG_OUT_STATUS_REG).check_access(&registry), Ok(()));
+		assert_eq!(
+			csr(LoadStore::Store, DBG_OUT_STATUS_REG).check_access(&registry),
+			Err(AccessViolation::StoreToReadOnly { addr: DBG_OUT_STATUS_REG, name: "dbg_out_status".into() }),
+		);
+		assert_eq!(
+			csr(LoadStore::Load, DBG_OUT_CHAR_OUT0_REG).check_access(&registry),
+			Err(AccessViolation::LoadFromWriteOnly { addr: DBG_OUT_CHAR_OUT0_REG, name: "dbg_out_char_out0".into() }),
+		);
+		let byte = |op, imm| Instruction { op: LoadStoreOp { op, width: Width::Byte }, ..csr(op, imm) };
+		assert_eq!(byte(LoadStore::Store, DBG_OUT_CHAR_OUT0_REG).check_access(&registry), Ok(()));
+		// A word also covers the read only char_in0 following it
+		assert_eq!(
+			csr(LoadStore::Store, DBG_OUT_CHAR_OUT0_REG).check_access(&registry),
+			Err(AccessViolation::StoreToReadOnly { addr: DBG_OUT_CHAR_IN0_REG, name: "dbg_out_char_in0".into() }),
+		);
+		assert_eq!(
+			csr(LoadStore::Load, DBG_OUT_CHAR_OUT0_REG - 3).check_access(&registry),
+			Err(AccessViolation::LoadFromWriteOnly { addr: DBG_OUT_CHAR_OUT0_REG, name: "dbg_out_char_out0".into() }),
+		);
+		assert_eq!(byte(LoadStore::Load, DBG_OUT_CHAR_OUT0_REG - 1).check_access(&registry), Ok(()));
+		assert_eq!(csr(LoadStore::Load, ISR_ERR1_REG).check_access(&registry), Ok(()));
+		assert!(csr(LoadStore::Store, ISR_ERR1_REG + 2).check_access(&registry).is_err());
+		assert_eq!(csr(LoadStore::Store, CSR_PSR_REG).check_access(&registry), Ok(()));
+		assert_eq!(csr(LoadStore::Store, 0x3000).check_access(&registry), Ok(()));
+	}
+
+	#[test]
+	fn isr_slots() {
+		assert_eq!(isr_save_slot(Register::r0()), None);
+		assert_eq!(isr_save_slot(Register::r1()), Some(ISR_R1_REG));
+		assert_eq!(isr_save_slot(Register::r27()), Some(ISR_R27_REG));
+		assert_eq!(isr_save_slot(Register::sp()), Some(ISR_SP_REG));
+		assert_eq!(isr_save_slot(Register::fp()), Some(ISR_FP_REG));
+		assert_eq!(isr_save_slot(Register::lr()), Some(ISR_LR_REG));
+		assert_eq!(isr_save_slot(Register::pc()), Some(ISR_PC_REG));
+
+		let registry = Registry::builtin();
+		for reg in (1..32).filter_map(Register::new) {
+			let slot = isr_save_slot(reg).unwrap();
+			assert_eq!(isr_slot_register(slot), Some(reg));
+			assert_eq!(registry.register_at(slot).unwrap().block.name, "isr");
+		}
+
+		assert_eq!(isr_slot_register(ISR_BASE_REG), None);
+		assert_eq!(isr_slot_register(ISR_R1_REG + 1), None);
+	}
+
+	#[test]
+	fn display_with() {
+		let registry = Registry::builtin();
+		let display = |op, width, imm| {
+			let i = Instruction { op: LoadStoreOp { op, width }, reg: Register::r4(), imm };
+			i.display_with(&registry).to_string()
+		};
+
+		assert_eq!(display(LoadStore::Load, Width::Word, ISR_ERR1_REG), "csr.ld r4, isr.err1");
+		assert_eq!(display(LoadStore::Store, Width::Byte, DBG_OUT_CHAR_OUT0_REG), "csr.st.b r4, dbg_out.char_out0");
+		assert_eq!(display(LoadStore::Load, Width::Word, ISR_ERR1_REG + 1), "csr.ld r4, 0x45");
+		assert_eq!(display(LoadStore::Load, Width::Word, 0x3000), "csr.ld r4, 0x3000");
+	}
+}
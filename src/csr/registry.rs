@@ -1,17 +1,107 @@
 use crate::Width;
 
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
+use xml::common::Position;
+use xml::reader::{EventReader, XmlEvent};
 use xmltree::*;
 
+use super::IMM_BITS;
 use super::regs::CSR_BLOCK_SIZE;
 
+/// Accesses allowed to a register or field
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Access {
+	#[default]
+	ReadWrite,
+	ReadOnly,
+	WriteOnly,
+	/// Writing 1 clears a bit, writing 0 leaves it unchanged
+	WriteOneToClear,
+}
+
+impl Access {
+	/// Parses the XML `access` attribute: `rw`, `ro`, `wo` or `w1c`
+	pub fn from_attribute(value: &str) -> Option<Access> {
+		match value {
+			"rw" => Some(Access::ReadWrite),
+			"ro" => Some(Access::ReadOnly),
+			"wo" => Some(Access::WriteOnly),
+			"w1c" => Some(Access::WriteOneToClear),
+			_ => None,
+		}
+	}
+
+	pub fn as_attribute(&self) -> &'static str {
+		match self {
+			Access::ReadWrite => "rw",
+			Access::ReadOnly => "ro",
+			Access::WriteOnly => "wo",
+			Access::WriteOneToClear => "w1c",
+		}
+	}
+}
+
+/// Group of bits within a register
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Field {
+	pub name: String,
+	pub lsb: u32,
+	pub width: u32,
+	pub access: Access,
+	pub reset: u32,
+}
+
+impl Field {
+	/// Mask of the field's bits in place, bits past the top of a `u32` are dropped
+	pub fn mask(&self) -> u32 {
+		let bits = u32::MAX.checked_shr(32u32.saturating_sub(self.width)).unwrap_or(0);
+		bits.checked_shl(self.lsb).unwrap_or(0)
+	}
+
+	/// Field value contained in the raw register value `value`
+	pub fn extract(&self, value: u32) -> u32 {
+		(value & self.mask()).checked_shr(self.lsb).unwrap_or(0)
+	}
+
+	/// Replaces the field in `value` with `field`, excess high bits of `field` are dropped
+	pub fn insert(&self, value: u32, field: u32) -> u32 {
+		(value & !self.mask()) | (field.checked_shl(self.lsb).unwrap_or(0) & self.mask())
+	}
+}
+
 #[derive(Debug)]
 pub struct Register {
 	pub name: String,
 	pub offset: u32,
 	pub width: Width,
 	pub aliases: Vec<String>,
+	pub fields: Vec<Field>,
+	pub access: Access,
+	/// Value after reset, taken from the fields when not given for the whole register
+	pub reset: u32,
+	/// Reading the register changes device state, e.g. consumes an input character
+	pub read_side_effects: bool,
+}
+
+impl Register {
+	pub fn field(&self, name: &str) -> Option<&Field> {
+		self.fields.iter().find(|f| f.name.eq_ignore_ascii_case(name))
+	}
+
+	/// Value of field `name` in `value`
+	pub fn extract(&self, name: &str, value: u32) -> Option<u32> {
+		self.field(name).map(|f| f.extract(value))
+	}
+
+	/// `value` with field `name` replaced by `field`
+	pub fn insert(&self, name: &str, value: u32, field: u32) -> Option<u32> {
+		self.field(name).map(|f| f.insert(value, field))
+	}
 }
 
 #[derive(Debug)]
@@ -20,80 +110,712 @@ pub struct Block {
 	pub base: u32,
 	pub count: u32,
 	pub registers: Vec<Register>,
+	/// Block this one is placed after, if its base was given by name
+	pub relative_to: Option<String>,
 }
 
-#[derive(Debug)]
+impl Block {
+	/// Size in bytes, saturating for blocks too large for the CSR space
+	pub fn size(&self) -> u32 {
+		self.count.saturating_mul(CSR_BLOCK_SIZE)
+	}
+
+	/// Address just past the block, `None` if it runs past the top of the CSR space
+	///
+	/// CSR instructions only have `IMM_BITS` of address, nothing above that can be reached.
+	pub fn end(&self) -> Option<u32> {
+		let end = self.base.checked_add(self.count.checked_mul(CSR_BLOCK_SIZE)?)?;
+		(end <= 1 << IMM_BITS).then_some(end)
+	}
+}
+
+#[derive(Debug, Default)]
 pub struct Registry {
+	/// Changes made after the first register lookup aren't seen by it, see `index`
 	pub blocks: HashMap<String, Block>,
+	index: OnceLock<Vec<IndexEntry>>,
+}
+
+/// Position of a register in `Registry::blocks`, ordered by address
+#[derive(Debug)]
+struct IndexEntry {
+	address: u32,
+	block: String,
+	register: usize,
+}
+
+/// Problem found by `Registry::validate`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Diagnostic {
+	/// Register extends past the end of its block
+	OutOfBounds {
+		block: String,
+		register: String,
+		offset: u32,
+		size: u32,
+	},
+	BlockOverlap {
+		first: String,
+		second: String,
+	},
+	RegisterOverlap {
+		block: String,
+		first: String,
+		second: String,
+	},
+	/// Field is empty or extends past the width of its register
+	FieldOutOfBounds {
+		register: String,
+		field: String,
+	},
+	FieldOverlap {
+		register: String,
+		first: String,
+		second: String,
+	},
+	/// Qualified name or alias that refers to more than one register
+	DuplicateName(String),
+	/// Blocks whose relative bases refer back to themselves
+	Cycle(Vec<String>),
+	/// Block extends past the top of the CSR address space
+	BlockTooLarge(String),
+}
+
+impl fmt::Display for Diagnostic {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Diagnostic::OutOfBounds { block, register, offset, size } => {
+				write!(f, "register {register} at offset {offset:#x} does not fit in block {block} of size {size:#x}")
+			},
+			Diagnostic::BlockOverlap { first, second } => write!(f, "blocks {first} and {second} overlap"),
+			Diagnostic::RegisterOverlap { block, first, second } => {
+				write!(f, "registers {first} and {second} in block {block} overlap")
+			},
+			Diagnostic::FieldOutOfBounds { register, field } => {
+				write!(f, "field {field} does not fit in register {register}")
+			},
+			Diagnostic::FieldOverlap { register, first, second } => {
+				write!(f, "fields {first} and {second} in register {register} overlap")
+			},
+			Diagnostic::DuplicateName(name) => write!(f, "name {name} is used more than once"),
+			Diagnostic::Cycle(blocks) => write!(f, "block bases form a cycle: {}", blocks.join(" -> ")),
+			Diagnostic::BlockTooLarge(block) => write!(f, "block {block} extends past the end of the CSR space"),
+		}
+	}
+}
+
+/// A register together with the block containing it
+#[derive(Clone, Copy, Debug)]
+pub struct RegisterRef<'a> {
+	pub block: &'a Block,
+	pub register: &'a Register,
+}
+
+impl RegisterRef<'_> {
+	/// Absolute CSR address
+	pub fn address(&self) -> u32 {
+		// Only wraps in registries that fail validation
+		self.block.base.wrapping_add(self.register.offset)
+	}
+
+	/// Name in the form `block_reg`, matching the generated constants
+	pub fn qualified_name(&self) -> String {
+		format!("{}_{}", self.block.name, self.register.name)
+	}
+
+	fn is_named(&self, name: &str) -> bool {
+		// `get` as `name` isn't necessarily split on a character boundary
+		let len = self.block.name.len();
+		let qualified = name.get(..len).is_some_and(|block| block.eq_ignore_ascii_case(&self.block.name))
+			&& matches!(name.as_bytes().get(len), Some(b'_' | b'.'))
+			&& name[len + 1..].eq_ignore_ascii_case(&self.register.name);
+
+		qualified || self.register.aliases.iter().any(|a| a.eq_ignore_ascii_case(name))
+	}
 }
 
 impl Registry {
 	pub fn new() -> Registry {
 		Registry {
 			blocks: HashMap::new(),
+			index: OnceLock::new(),
+		}
+	}
+
+	/// Registry described by `regs/regs.xml`, the same one `csr::regs` is generated from
+	pub fn builtin() -> Registry {
+		let mut parser = RegistryParser::new();
+		parser.load_str(include_str!("regs/regs.xml")).unwrap();
+		parser.finish().unwrap()
+	}
+
+	/// Blocks ordered by base address
+	pub fn blocks_in_order(&self) -> Vec<&Block> {
+		let mut blocks: Vec<&Block> = self.blocks.values().collect();
+		blocks.sort_by(|a, b| (a.base, &a.name).cmp(&(b.base, &b.name)));
+		blocks
+	}
+
+	/// Every register sorted by address, built by the first lookup
+	fn index(&self) -> &[IndexEntry] {
+		self.index.get_or_init(|| {
+			let mut index: Vec<_> = self.blocks.iter()
+				.flat_map(|(name, block)| (0..block.registers.len()).map(|register| IndexEntry {
+					address: block.base.wrapping_add(block.registers[register].offset),
+					block: name.clone(),
+					register,
+				}))
+				.collect();
+			index.sort_by(|a, b| {
+				let name = |e: &IndexEntry| self.blocks[&e.block].registers[e.register].name.as_str();
+				(a.address, &a.block, name(a)).cmp(&(b.address, &b.block, name(b)))
+			});
+			index
+		})
+	}
+
+	fn entry(&self, entry: &IndexEntry) -> RegisterRef<'_> {
+		let block = &self.blocks[&entry.block];
+		RegisterRef { block, register: &block.registers[entry.register] }
+	}
+
+	/// All registers ordered by address
+	pub fn iter(&self) -> impl Iterator<Item = RegisterRef<'_>> {
+		self.index().iter().map(|entry| self.entry(entry))
+	}
+
+	/// Finds a register by qualified name (`isr_err1` or `isr.err1`) or alias, ignoring case
+	pub fn lookup(&self, name: &str) -> Option<RegisterRef<'_>> {
+		self.iter().find(|r| r.is_named(name))
+	}
+
+	/// Absolute address of the register called `name`, see `lookup`
+	pub fn address_of(&self, name: &str) -> Option<u32> {
+		self.lookup(name).map(|r| r.address())
+	}
+
+	/// Register occupying `addr`, which doesn't have to be its first byte
+	pub fn register_at(&self, addr: u32) -> Option<RegisterRef<'_>> {
+		self.registers_in(addr, 1).next()
+	}
+
+	/// Registers overlapping the `len` bytes starting at `addr`, in address order
+	pub fn registers_in(&self, addr: u32, len: u32) -> impl Iterator<Item = RegisterRef<'_>> {
+		// Widened so ranges at the top of the address space don't wrap
+		let end = addr as u64 + len as u64;
+		let index = self.index();
+		// No register is wider than a word, so anything starting a word or more before `addr` ends before it
+		let first = index.partition_point(|e| e.address as u64 + Width::Word.to_len() as u64 <= addr as u64);
+		index[first..].iter()
+			.take_while(move |e| (e.address as u64) < end)
+			.map(|entry| self.entry(entry))
+			.filter(move |r| (addr as u64) < r.address() as u64 + r.register.width.to_len() as u64)
+	}
+
+	/// Checks the registry for problems that would produce wrong or ambiguous addresses
+	pub fn validate(&self) -> Vec<Diagnostic> {
+		let mut diagnostics = Vec::new();
+		let blocks = self.blocks_in_order();
+
+		self.check_cycles(&blocks, &mut diagnostics);
+
+		for block in blocks.iter().filter(|b| b.end().is_none()) {
+			diagnostics.push(Diagnostic::BlockTooLarge(block.name.clone()));
+		}
+
+		for (i, first) in blocks.iter().enumerate() {
+			for second in &blocks[i + 1..] {
+				let (Some(first_end), Some(second_end)) = (first.end(), second.end()) else {
+					continue;
+				};
+				if first_end > second.base && second_end > first.base {
+					diagnostics.push(Diagnostic::BlockOverlap {
+						first: first.name.clone(),
+						second: second.name.clone(),
+					});
+				}
+			}
+		}
+
+		for block in &blocks {
+			for (i, first) in block.registers.iter().enumerate() {
+				let end = first.offset.checked_add(first.width.to_len());
+				if end.is_none_or(|end| end > block.size()) {
+					diagnostics.push(Diagnostic::OutOfBounds {
+						block: block.name.clone(),
+						register: first.name.clone(),
+						offset: first.offset,
+						size: block.size(),
+					});
+				}
+
+				let Some(end) = end else {
+					continue;
+				};
+				for second in &block.registers[i + 1..] {
+					let second_end = second.offset.checked_add(second.width.to_len());
+					if end > second.offset && second_end.is_none_or(|second_end| second_end > first.offset) {
+						diagnostics.push(Diagnostic::RegisterOverlap {
+							block: block.name.clone(),
+							first: first.name.clone(),
+							second: second.name.clone(),
+						});
+					}
+				}
+			}
+		}
+
+		for reg in self.iter() {
+			Self::check_fields(reg, &mut diagnostics);
+		}
+
+		// Names are compared ignoring case since they also become constants
+		let mut names = HashSet::new();
+		let mut duplicates = HashSet::new();
+		for reg in self.iter() {
+			let aliases = reg.register.aliases.iter().cloned();
+			let fields = reg.register.fields.iter().map(|f| format!("{}.{}", reg.qualified_name(), f.name));
+			for name in std::iter::once(reg.qualified_name()).chain(aliases).chain(fields) {
+				let name = name.to_lowercase();
+				if !names.insert(name.clone()) && duplicates.insert(name.clone()) {
+					diagnostics.push(Diagnostic::DuplicateName(name));
+				}
+			}
+		}
+
+		diagnostics
+	}
+
+	fn check_fields(reg: RegisterRef, diagnostics: &mut Vec<Diagnostic>) {
+		let bits = reg.register.width.to_len() * 8;
+		let fields = &reg.register.fields;
+		for (i, first) in fields.iter().enumerate() {
+			if first.width == 0 || first.lsb.checked_add(first.width).is_none_or(|end| end > bits) {
+				diagnostics.push(Diagnostic::FieldOutOfBounds {
+					register: reg.qualified_name(),
+					field: first.name.clone(),
+				});
+				// The mask of an out of bounds field isn't meaningful
+				continue;
+			}
+
+			for second in &fields[i + 1..] {
+				let valid = second.width != 0 && second.lsb.checked_add(second.width).is_some_and(|end| end <= bits);
+				if valid && first.mask() & second.mask() != 0 {
+					diagnostics.push(Diagnostic::FieldOverlap {
+						register: reg.qualified_name(),
+						first: first.name.clone(),
+						second: second.name.clone(),
+					});
+				}
+			}
+		}
+	}
+
+	fn check_cycles(&self, blocks: &[&Block], diagnostics: &mut Vec<Diagnostic>) {
+		let mut reported = HashSet::new();
+		for block in blocks {
+			let mut chain = vec![block.name.clone()];
+			let mut current = *block;
+			while let Some(next) = current.relative_to.as_ref().and_then(|name| self.blocks.get(name)) {
+				if let Some(start) = chain.iter().position(|name| *name == next.name) {
+					let mut cycle = chain.split_off(start);
+					// Start every cycle from the same block so it's only reported once
+					let min = (0..cycle.len()).min_by_key(|&i| &cycle[i]).unwrap();
+					cycle.rotate_left(min);
+					if reported.insert(cycle.clone()) {
+						diagnostics.push(Diagnostic::Cycle(cycle));
+					}
+					break;
+				}
+
+				chain.push(next.name.clone());
+				current = next;
+			}
 		}
 	}
 }
 
+/// Element with the line it was read from, elements built with `xmltree` have no line
 #[derive(Debug)]
-pub struct RegistryParser {
-	registry: Registry,
-	relative_blocks: HashMap<String, String>,
+struct Node {
+	name: String,
+	attributes: HashMap<String, String>,
+	children: Vec<Node>,
+	line: Option<u64>,
 }
 
-#[derive(Debug)]
-pub enum Error {
-	InvalidNode,
+impl From<&Element> for Node {
+	fn from(element: &Element) -> Node {
+		Node {
+			name: element.name.clone(),
+			attributes: element.attributes.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+			children: element.children.iter().filter_map(|n| n.as_element()).map(Node::from).collect(),
+			line: None,
+		}
+	}
+}
+
+impl Node {
+	fn read(source: &str, file: Option<&Path>) -> Result<Node, Error> {
+		let mut reader = EventReader::from_str(source);
+		let mut stack: Vec<Node> = Vec::new();
+
+		loop {
+			let event = reader.next().map_err(|e| Error {
+				kind: ErrorKind::Xml(e.msg().to_string()),
+				location: Box::new(Location {
+					file: file.map(Path::to_path_buf),
+					line: Some(e.position().row + 1),
+					..Location::default()
+				}),
+			})?;
+
+			match event {
+				XmlEvent::StartElement { name, attributes, .. } => stack.push(Node {
+					name: name.local_name,
+					attributes: attributes.into_iter().map(|a| (a.name.local_name, a.value)).collect(),
+					children: Vec::new(),
+					line: Some(reader.position().row + 1),
+				}),
+				XmlEvent::EndElement { .. } => {
+					// The reader checks that elements are balanced
+					let node = stack.pop().unwrap();
+					match stack.last_mut() {
+						Some(parent) => parent.children.push(node),
+						None => return Ok(node),
+					}
+				},
+				_ => {},
+			}
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+	/// Element that isn't allowed where it appears
+	InvalidNode(String),
 	MissingAttribute,
-	InvalidBaseAddress,
+	InvalidValue(String),
 	UnresolvedBlock(String),
+	DuplicateBlock(String),
+	/// File that includes itself, directly or through other files
+	IncludeCycle(PathBuf),
+	Io(String),
+	Xml(String),
+}
+
+/// Where an error was found, parts that don't apply or aren't known are `None`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Location {
+	pub file: Option<PathBuf>,
+	pub line: Option<u64>,
+	pub block: Option<String>,
+	pub register: Option<String>,
+	pub attribute: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+	pub kind: ErrorKind,
+	pub location: Box<Location>,
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let location = &self.location;
+		match (&location.file, location.line) {
+			(Some(file), Some(line)) => write!(f, "{}:{line}: ", file.display())?,
+			(Some(file), None) => write!(f, "{}: ", file.display())?,
+			(None, Some(line)) => write!(f, "line {line}: ")?,
+			(None, None) => {},
+		}
+
+		let context: Vec<String> = [("block", &location.block), ("register", &location.register), ("attribute", &location.attribute)]
+			.into_iter()
+			.filter_map(|(what, name)| name.as_ref().map(|name| format!("{what} {name}")))
+			.collect();
+		if !context.is_empty() {
+			write!(f, "{}: ", context.join(", "))?;
+		}
+
+		match &self.kind {
+			ErrorKind::InvalidNode(name) => write!(f, "unexpected element <{name}>"),
+			ErrorKind::MissingAttribute => write!(f, "missing or invalid attribute"),
+			ErrorKind::InvalidValue(value) => write!(f, "invalid value \"{value}\""),
+			ErrorKind::UnresolvedBlock(name) => write!(f, "no block named {name}"),
+			ErrorKind::DuplicateBlock(name) => write!(f, "block {name} is defined more than once"),
+			ErrorKind::IncludeCycle(path) => write!(f, "{} includes itself", path.display()),
+			ErrorKind::Io(err) => write!(f, "{err}"),
+			ErrorKind::Xml(err) => write!(f, "{err}"),
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+/// What's being parsed, used to give errors context
+#[derive(Clone, Copy, Default)]
+struct Scope<'a> {
+	file: Option<&'a Path>,
+	block: Option<&'a str>,
+	register: Option<&'a str>,
+}
+
+impl Scope<'_> {
+	fn location(&self, node: &Node, attribute: Option<&str>) -> Location {
+		Location {
+			file: self.file.map(Path::to_path_buf),
+			line: node.line,
+			block: self.block.map(str::to_string),
+			register: self.register.map(str::to_string),
+			attribute: attribute.map(str::to_string),
+		}
+	}
+
+	fn error(&self, node: &Node, attribute: Option<&str>, kind: ErrorKind) -> Error {
+		Error {
+			kind,
+			location: Box::new(self.location(node, attribute)),
+		}
+	}
+
+	fn expect(&self, node: &Node, name: &str) -> Result<(), Error> {
+		if node.name != name {
+			Err(self.error(node, None, ErrorKind::InvalidNode(node.name.clone())))
+		} else {
+			Ok(())
+		}
+	}
+
+	fn attribute<'n>(&self, node: &'n Node, name: &str) -> Result<&'n String, Error> {
+		node.attributes.get(name)
+			.ok_or_else(|| self.error(node, Some(name), ErrorKind::MissingAttribute))
+	}
+
+	fn invalid(&self, node: &Node, name: &str, value: &str) -> Error {
+		self.error(node, Some(name), ErrorKind::InvalidValue(value.to_string()))
+	}
+
+	/// Parses a decimal or `0x` prefixed hex attribute
+	fn number(&self, node: &Node, name: &str) -> Result<u32, Error> {
+		let value = self.attribute(node, name)?;
+		let parsed = match value.strip_prefix("0x") {
+			Some(hex) => u32::from_str_radix(hex, 16),
+			None => value.parse(),
+		};
+		parsed.map_err(|_| self.invalid(node, name, value))
+	}
+
+	fn optional_number(&self, node: &Node, name: &str) -> Result<Option<u32>, Error> {
+		if node.attributes.contains_key(name) {
+			self.number(node, name).map(Some)
+		} else {
+			Ok(None)
+		}
+	}
+
+	fn access(&self, node: &Node) -> Result<Access, Error> {
+		match node.attributes.get("access") {
+			Some(access) => Access::from_attribute(access).ok_or_else(|| self.invalid(node, "access", access)),
+			None => Ok(Access::default()),
+		}
+	}
+
+	fn field(&self, node: &Node) -> Result<Field, Error> {
+		let lsb = self.number(node, "lsb")?;
+		if lsb >= 32 {
+			return Err(self.invalid(node, "lsb", &node.attributes["lsb"]));
+		}
+		let width = self.number(node, "width")?;
+		if width == 0 || lsb.checked_add(width).is_none_or(|end| end > 32) {
+			return Err(self.invalid(node, "width", &node.attributes["width"]));
+		}
+
+		Ok(Field {
+			name: self.attribute(node, "name")?.clone(),
+			lsb,
+			width,
+			access: self.access(node)?,
+			reset: self.optional_number(node, "reset")?.unwrap_or(0),
+		})
+	}
+
+	fn register(&self, node: &Node) -> Result<Register, Error> {
+		self.expect(node, "reg")?;
+		let name = self.attribute(node, "name")?;
+		let scope = Scope { register: Some(name), ..*self };
+
+		let offset_string = scope.attribute(node, "offset")?;
+		let offset = offset_string.strip_prefix("0x")
+			.and_then(|hex| u32::from_str_radix(hex, 16).ok())
+			.ok_or_else(|| scope.invalid(node, "offset", offset_string))?;
+
+		let size = scope.attribute(node, "size")?;
+		let width = match size.as_str() {
+			"byte" => Width::Byte,
+			"short" => Width::Short,
+			"word" => Width::Word,
+			_ => return Err(scope.invalid(node, "size", size)),
+		};
+
+		// Handle aliases and fields
+		let mut aliases = Vec::new();
+		let mut fields = Vec::new();
+		for child in &node.children {
+			match child.name.as_str() {
+				"alias" => aliases.push(scope.attribute(child, "name")?.clone()),
+				"field" => fields.push(scope.field(child)?),
+				_ => return Err(scope.error(child, None, ErrorKind::InvalidNode(child.name.clone()))),
+			}
+		}
+
+		let reset = match scope.optional_number(node, "reset")? {
+			Some(reset) => reset,
+			None => fields.iter().fold(0, |value, field| field.insert(value, field.reset)),
+		};
+		let read_side_effects = match node.attributes.get("side-effects").map(String::as_str) {
+			Some("read") => true,
+			Some("none") | None => false,
+			Some(other) => return Err(scope.invalid(node, "side-effects", other)),
+		};
+
+		Ok(Register {
+			aliases,
+			name: name.clone(),
+			offset,
+			width,
+			fields,
+			access: scope.access(node)?,
+			reset,
+			read_side_effects,
+		})
+	}
+}
+
+#[derive(Debug, Default)]
+pub struct RegistryParser {
+	registry: Registry,
+	relative_blocks: HashMap<String, String>,
+	/// Where each block was defined
+	locations: HashMap<String, Location>,
+	files: Vec<PathBuf>,
 }
 
 impl RegistryParser {
 	pub fn new() -> RegistryParser {
-		RegistryParser {
-			registry: Registry::new(),
-			relative_blocks: HashMap::new(),
-		}
+		RegistryParser::default()
+	}
+
+	/// Files read by `load_file`, including those reached through `<include>`
+	pub fn files(&self) -> &[PathBuf] {
+		&self.files
+	}
+
+	/// Loads a file containing a `<registry>` or a single `<block>`
+	///
+	/// `<include file="...">` elements in a registry are resolved relative to the including file.
+	pub fn load_file(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+		self.load(path.as_ref(), &mut Vec::new())
+	}
+
+	/// Loads a `<registry>` or a single `<block>` from a string, includes are relative to the working directory
+	pub fn load_str(&mut self, source: &str) -> Result<(), Error> {
+		let root = Node::read(source, None)?;
+		self.root(&root, Scope::default(), &mut Vec::new())
+	}
+
+	/// Adds every block of a `<registry>` element
+	pub fn add_registry(&mut self, node: &Element) -> Result<(), Error> {
+		let node = Node::from(node);
+		Scope::default().expect(&node, "registry")?;
+		self.registry_node(&node, Scope::default(), &mut Vec::new())
 	}
 
 	pub fn add_block(&mut self, node: &Element) -> Result<(), Error> {
-		if node.name != "block" {
-			return Err(Error::InvalidNode);
-		}
+		self.block(&Node::from(node), Scope::default())
+	}
+
+	fn load(&mut self, path: &Path, includes: &mut Vec<PathBuf>) -> Result<(), Error> {
+		let io_error = |e: std::io::Error| Error {
+			kind: ErrorKind::Io(e.to_string()),
+			location: Box::new(Location {
+				file: Some(path.to_path_buf()),
+				..Location::default()
+			}),
+		};
 
-		if !node.attributes.contains_key("base") {
-			return Err(Error::MissingAttribute);
+		let canonical = path.canonicalize().map_err(io_error)?;
+		let source = fs::read_to_string(path).map_err(io_error)?;
+		self.files.push(path.to_path_buf());
+
+		let scope = Scope {
+			file: Some(path),
+			..Scope::default()
+		};
+		let root = Node::read(&source, scope.file)?;
+
+		includes.push(canonical);
+		let result = self.root(&root, scope, includes);
+		includes.pop();
+		result
+	}
+
+	fn root(&mut self, node: &Node, scope: Scope, includes: &mut Vec<PathBuf>) -> Result<(), Error> {
+		match node.name.as_str() {
+			"registry" => self.registry_node(node, scope, includes),
+			_ => self.block(node, scope),
 		}
+	}
 
-		if !node.attributes.contains_key("name") {
-			return Err(Error::MissingAttribute);
+	fn registry_node(&mut self, node: &Node, scope: Scope, includes: &mut Vec<PathBuf>) -> Result<(), Error> {
+		for child in &node.children {
+			if child.name == "include" {
+				let file = scope.attribute(child, "file")?;
+				let path = match scope.file.and_then(Path::parent) {
+					Some(dir) => dir.join(file),
+					None => PathBuf::from(file),
+				};
+				let canonical = path.canonicalize()
+					.map_err(|e| scope.error(child, Some("file"), ErrorKind::Io(e.to_string())))?;
+				if includes.contains(&canonical) {
+					return Err(scope.error(child, Some("file"), ErrorKind::IncludeCycle(path)));
+				}
+				self.load(&path, includes)?;
+			} else {
+				self.block(child, scope)?;
+			}
 		}
+		Ok(())
+	}
 
-		if !node.attributes.contains_key("count") {
-			return Err(Error::MissingAttribute);
+	fn block(&mut self, node: &Node, scope: Scope) -> Result<(), Error> {
+		scope.expect(node, "block")?;
+
+		let block_name = scope.attribute(node, "name")?.clone();
+		let scope = Scope { block: Some(&block_name), ..scope };
+		if self.registry.blocks.contains_key(&block_name) {
+			return Err(scope.error(node, Some("name"), ErrorKind::DuplicateBlock(block_name.clone())));
 		}
 
-		let block_name = node.attributes.get("name").unwrap().clone();
-		let count = node.attributes.get("count")
-			.map(|s| s.parse::<u32>().ok())
-			.flatten()
-			.ok_or(Error::MissingAttribute)?;
-			
-		let base = node.attributes.get("base")
-			.map(|s| s.strip_prefix("0x"))
-			.flatten()
-			.and_then(
-				|x| u32::from_str_radix(&x, 16).ok()
-			);
+		let count_string = scope.attribute(node, "count")?;
+		let count = count_string.parse::<u32>()
+			.map_err(|_| scope.invalid(node, "count", count_string))?;
+
+		let base_string = scope.attribute(node, "base")?;
+		let base = base_string.strip_prefix("0x")
+			.and_then(|x| u32::from_str_radix(x, 16).ok());
+		let relative_to = base.is_none().then(|| base_string.clone());
 		let base = if let Some(addr) = base {
 			addr
 		} else {
 			// This is a relative block
-			let base_name = node.attributes.get("base").unwrap().clone();
+			let base_name = base_string.clone();
 			if let Some(block) = self.registry.blocks.get(&base_name) {
 				if !self.relative_blocks.contains_key(&base_name) {
-					block.base + block.count * CSR_BLOCK_SIZE
+					// A block running off the end is reported by validation
+					block.end().unwrap_or(u32::MAX)
 				} else {
 					self.relative_blocks.insert(block_name.clone(), base_name);
 					0
@@ -104,63 +826,19 @@ impl RegistryParser {
 			}
 		};
 
-		let mut block = Block {
+		let registers = node.children.iter()
+			.map(|register| scope.register(register))
+			.collect::<Result<Vec<_>, _>>()?;
+
+		let block = Block {
 			name: block_name.clone(),
 			base,
 			count,
-			registers: Vec::new(),
+			registers,
+			relative_to,
 		};
 
-		for node in &node.children {
-			if let Some(register) = node.as_element() {
-				if register.name != "reg" {
-					return Err(Error::InvalidNode);
-				}
-
-				let reg_name = register.attributes.get("name")
-					.ok_or(Error::MissingAttribute)?.clone();
-				let offset_string = register.attributes.get("offset")
-					.map(|s| s.strip_prefix("0x"))
-					.flatten()
-					.ok_or(Error::MissingAttribute)?;
-				let offset = u32::from_str_radix(offset_string, 16)
-					.map_err(|_| Error::InvalidBaseAddress)?;
-
-				let mut qualified_name = block_name.clone() + "_";
-				qualified_name.push_str(&reg_name);
-
-				let width = register.attributes.get("size").and_then(|w| match w.as_str() {
-					"byte" => Some(Width::Byte),
-					"short" => Some(Width::Short),
-					"word" => Some(Width::Word),
-					_ => None,
-				}).ok_or(Error::MissingAttribute)?;
-
-				// Handle alias
-				let mut aliases = Vec::new();
-				for alias in register.children.iter() {
-					if let Some(alias) = alias.as_element() {
-						if alias.name != "alias" {
-							Err(Error::InvalidNode)?;
-						}
-
-						let name = alias.attributes.get("name")
-							.ok_or(Error::MissingAttribute)?;
-						aliases.push(name.clone());
-					}
-				}
-
-				let reg = Register {
-					aliases,
-					name: reg_name.clone(),
-					offset,
-					width,
-				};
-
-				block.registers.push(reg);
-			}
-		}
-
+		self.locations.insert(block_name.clone(), scope.location(node, Some("base")));
 		self.registry.blocks.insert(block_name, block);
 		Ok(())
 	}
@@ -183,19 +861,26 @@ impl RegistryParser {
 
 			let referenced_name = self.relative_blocks.get(&block_name).unwrap();
 			let referenced = self.registry.blocks.get(referenced_name)
-				.ok_or(Error::UnresolvedBlock(block_name.clone()))?;
-			let base = referenced.base + referenced.count * CSR_BLOCK_SIZE;
+				.ok_or_else(|| self.unresolved(&block_name, referenced_name))?;
+			let base = referenced.end().unwrap_or(u32::MAX);
 
 			if let Some(block) = self.registry.blocks.get_mut(&block_name) {
 				block.base = base;
 			} else {
-				return Err(Error::UnresolvedBlock(block_name));
+				return Err(self.unresolved(&block_name, &block_name));
 			}
 		}
 
 		Ok(())
 	}
 
+	fn unresolved(&self, block_name: &str, referenced_name: &str) -> Error {
+		Error {
+			kind: ErrorKind::UnresolvedBlock(referenced_name.to_string()),
+			location: Box::new(self.locations.get(block_name).cloned().unwrap_or_default()),
+		}
+	}
+
 	fn visit_block(&self, block_name: &String, visited: &mut HashSet<String>, order: &mut Vec<String>) -> Result<(), Error> {
 		visited.insert(block_name.clone());
 		if let Some(referenced_block) = self.relative_blocks.get(block_name) {
@@ -242,4 +927,312 @@ mod test {
 		let registry = parser.finish();
 		println!("Registry: {registry:?}");
 	}
+
+	#[test]
+	fn lookup() {
+		use crate::csr::regs::*;
+
+		let registry = Registry::builtin();
+
+		assert_eq!(registry.address_of("isr_err1"), Some(ISR_ERR1_REG));
+		assert_eq!(registry.address_of("ISR.ERR1"), Some(ISR_ERR1_REG));
+		assert_eq!(registry.address_of("dbg_out_char_in0"), Some(DBG_OUT_CHAR_IN0_REG));
+		assert_eq!(registry.address_of("csr_psr"), Some(CSR_PSR_REG));
+		assert_eq!(registry.address_of("isr_err3"), None);
+		assert_eq!(registry.address_of("isr"), None);
+		assert!(registry.lookup("ps€x").is_none());
+		assert!(registry.lookup("isr€").is_none());
+
+		let reg = registry.register_at(0x141).unwrap();
+		assert_eq!(reg.qualified_name(), "dbg_out_char_in0");
+		assert_eq!(reg.register.width, Width::Byte);
+		assert_eq!(registry.register_at(ISR_ERR2_REG + 3).unwrap().qualified_name(), "isr_err2");
+		assert!(registry.register_at(0x142).is_none());
+		assert_eq!(registry.register_at(0).unwrap().register.aliases, ["csr_psr"]);
+
+		let addresses: Vec<u32> = registry.iter().map(|r| r.address()).collect();
+		assert_eq!(addresses.first(), Some(&PSR_PSR0_REG));
+		assert_eq!(addresses.last(), Some(&DBG_OUT_GPIO_IN0_REG));
+		assert!(addresses.windows(2).all(|w| w[0] < w[1]));
+
+		for reg in registry.iter() {
+			assert_eq!(registry.address_of(&reg.qualified_name()), Some(reg.address()));
+			assert_eq!(registry.register_at(reg.address()).unwrap().address(), reg.address());
+		}
+
+		let names = |addr, len| registry.registers_in(addr, len).map(|r| r.qualified_name()).collect::<Vec<_>>();
+		assert_eq!(names(ISR_ERR1_REG + 2, 4), ["isr_err1", "isr_err2"]);
+		assert_eq!(names(DBG_OUT_CHAR_OUT0_REG - 3, 4), ["dbg_out_char_out0"]);
+		assert_eq!(names(DBG_OUT_CHAR_OUT0_REG + 1, 2), ["dbg_out_char_in0"]);
+		assert!(names(0x3c, 4).is_empty());
+		assert!(names(u32::MAX, 4).is_empty());
+	}
+
+	#[test]
+	fn fields() {
+		let registry = parse(r##"<registry>
+			<block name="a" base="0x0" count="1">
+				<reg name="x" offset="0x0" size="word">
+					<field name="low" lsb="0" width="4" reset="0xa" />
+					<field name="high" lsb="28" width="4" access="w1c" />
+					<field name="all" lsb="0" width="32" access="ro" />
+				</reg>
+			</block>
+		</registry>"##).unwrap();
+		let reg = registry.lookup("a_x").unwrap().register;
+
+		let low = reg.field("low").unwrap();
+		assert_eq!(low.reset, 0xa);
+		assert_eq!(low.access, Access::ReadWrite);
+		assert_eq!(low.mask(), 0xf);
+		assert_eq!(reg.field("HIGH").unwrap().access, Access::WriteOneToClear);
+		assert_eq!(reg.field("high").unwrap().mask(), 0xf000_0000);
+		assert_eq!(reg.field("all").unwrap().mask(), u32::MAX);
+
+		assert_eq!(reg.extract("low", 0x1234_5678), Some(0x8));
+		assert_eq!(reg.extract("high", 0x1234_5678), Some(0x1));
+		assert_eq!(reg.insert("high", 0x1234_5678, 0xfe), Some(0xe234_5678));
+		assert_eq!(reg.insert("low", 0, 0x13), Some(0x3));
+		assert_eq!(reg.extract("missing", 0), None);
+
+		let invalid = r##"<registry>
+			<block name="a" base="0x0" count="1">
+				<reg name="x" offset="0x0" size="word">
+					<field name="f" lsb="0" width="1" access="rx" />
+				</reg>
+			</block>
+		</registry>"##;
+		assert!(matches!(parse(invalid), Err(Error { kind: ErrorKind::InvalidValue(v), .. }) if v == "rx"));
+	}
+
+	#[test]
+	fn access() {
+		use crate::csr::regs::*;
+
+		let registry = Registry::builtin();
+		let reg = |addr| registry.register_at(addr).unwrap().register;
+
+		assert_eq!(reg(PSR_PSR0_REG).access, Access::ReadWrite);
+		assert_eq!(reg(DBG_OUT_STATUS_REG).access, Access::ReadOnly);
+		assert_eq!(reg(DBG_OUT_STATUS_REG).reset, 0x1);
+		assert_eq!(reg(DBG_OUT_CHAR_OUT0_REG).access, Access::WriteOnly);
+		assert!(reg(DBG_OUT_CHAR_IN0_REG).read_side_effects);
+		assert!(!reg(DBG_OUT_STATUS_REG).read_side_effects);
+
+		let registry = parse(r##"<registry>
+			<block name="a" base="0x0" count="1">
+				<reg name="x" offset="0x0" size="word" access="w1c" reset="0x80" />
+			</block>
+		</registry>"##).unwrap();
+		let x = registry.lookup("a_x").unwrap().register;
+		assert_eq!(x.access, Access::WriteOneToClear);
+		assert_eq!(x.reset, 0x80);
+	}
+
+	#[test]
+	fn psr_fields() {
+		use crate::Flags;
+
+		let registry = Registry::builtin();
+		let psr = registry.lookup("csr_psr").unwrap().register;
+		let flags = Flags { overflow: false, carry: true, zero: false, negative: true };
+		let value = flags.to_psr(0);
+
+		assert_eq!(psr.extract("overflow", value), Some(0));
+		assert_eq!(psr.extract("carry", value), Some(1));
+		assert_eq!(psr.extract("zero", value), Some(0));
+		assert_eq!(psr.extract("negative", value), Some(1));
+	}
+
+	fn parse(source: &str) -> Result<Registry, Error> {
+		let mut parser = RegistryParser::new();
+		parser.load_str(source)?;
+		parser.finish()
+	}
+
+	#[test]
+	fn errors() {
+		let err = parse(r##"<registry>
+			<block name="a" base="0x0" count="1">
+				<reg name="x" offset="0x0" size="word" />
+				<reg name="y" size="word" />
+			</block>
+		</registry>"##).unwrap_err();
+
+		assert_eq!(err.kind, ErrorKind::MissingAttribute);
+		assert_eq!(*err.location, Location {
+			file: None,
+			line: Some(4),
+			block: Some("a".into()),
+			register: Some("y".into()),
+			attribute: Some("offset".into()),
+		});
+		assert_eq!(err.to_string(), "line 4: block a, register y, attribute offset: missing or invalid attribute");
+
+		let err = parse(r##"<registry>
+			<block name="a" base="0x0" count="1">
+				<reg name="x" offset="0x0" size="word">
+					<field name="f" lsb="one" width="1" />
+				</reg>
+			</block>
+		</registry>"##).unwrap_err();
+		assert_eq!(err.to_string(), "line 4: block a, register x, attribute lsb: invalid value \"one\"");
+
+		let field = |attributes: &str| parse(&format!(r##"<registry>
+			<block name="a" base="0x0" count="1">
+				<reg name="x" offset="0x0" size="word">
+					<field name="f" {attributes} />
+				</reg>
+			</block>
+		</registry>"##)).unwrap_err().to_string();
+		assert_eq!(field(r#"lsb="4294967295" width="1""#),
+			"line 4: block a, register x, attribute lsb: invalid value \"4294967295\"");
+		assert_eq!(field(r#"lsb="0" width="0""#), "line 4: block a, register x, attribute width: invalid value \"0\"");
+		assert_eq!(field(r#"lsb="31" width="2""#), "line 4: block a, register x, attribute width: invalid value \"2\"");
+		assert_eq!(field(r#"lsb="1" width="0xffffffff""#),
+			"line 4: block a, register x, attribute width: invalid value \"0xffffffff\"");
+
+		let err = parse("<registry>\n<blok name=\"a\" />\n</registry>").unwrap_err();
+		assert_eq!(err.to_string(), "line 2: unexpected element <blok>");
+
+		let err = parse("<registry>\n\n<block name=\"a\" base=\"b\" count=\"1\" />\n</registry>").unwrap_err();
+		assert_eq!(err.to_string(), "line 3: block a, attribute base: no block named b");
+
+		let err = parse("<registry>\n<block>\n</registry>").unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::Xml(_)));
+		assert_eq!(err.location.line, Some(3));
+	}
+
+	#[test]
+	fn load_file() {
+		let dir = std::env::temp_dir().join(format!("bibe-registry-{}", std::process::id()));
+		fs::create_dir_all(dir.join("blocks")).unwrap();
+
+		fs::write(dir.join("root.xml"), r##"<registry>
+			<block name="a" base="0x0" count="1">
+				<reg name="x" offset="0x0" size="word" />
+			</block>
+			<include file="blocks/b.xml" />
+		</registry>"##).unwrap();
+		fs::write(dir.join("blocks/b.xml"), r##"<registry>
+			<include file="c.xml" />
+			<block name="b" base="a" count="1">
+				<reg name="y" offset="0x4" size="word" />
+			</block>
+		</registry>"##).unwrap();
+		fs::write(dir.join("blocks/c.xml"), r##"<block name="c" base="b" count="2">
+			<reg name="z" offset="0x0" size="byte" />
+		</block>"##).unwrap();
+
+		let mut parser = RegistryParser::new();
+		parser.load_file(dir.join("root.xml")).unwrap();
+		assert_eq!(parser.files().len(), 3);
+		let registry = parser.finish().unwrap();
+		assert_eq!(registry.address_of("a_x"), Some(0x0));
+		assert_eq!(registry.address_of("b_y"), Some(0x44));
+		assert_eq!(registry.address_of("c_z"), Some(0x80));
+
+		fs::write(dir.join("blocks/c.xml"), r##"<registry>
+			<include file="../root.xml" />
+		</registry>"##).unwrap();
+		let err = RegistryParser::new().load_file(dir.join("root.xml")).unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::IncludeCycle(_)));
+		assert_eq!(err.location.file, Some(dir.join("blocks/c.xml")));
+		assert_eq!(err.location.line, Some(2));
+
+		fs::write(dir.join("blocks/c.xml"), "<block name=\"c\" base=\"b\" count=\"1\">\n<reg name=\"z\" offset=\"0x0\" size=\"long\" />\n</block>").unwrap();
+		let err = RegistryParser::new().load_file(dir.join("root.xml")).unwrap_err();
+		assert_eq!(
+			err.to_string(),
+			format!("{}:2: block c, register z, attribute size: invalid value \"long\"", dir.join("blocks/c.xml").display()),
+		);
+
+		let err = RegistryParser::new().load_file(dir.join("missing.xml")).unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::Io(_)));
+
+		fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn validate() {
+		assert_eq!(Registry::builtin().validate(), []);
+
+		let registry = parse(r##"<registry>
+			<block name="a" base="0x0" count="1">
+				<reg name="x" offset="0x3C" size="word" />
+				<reg name="y" offset="0x3E" size="short" />
+				<reg name="z" offset="0x40" size="byte" />
+			</block>
+			<block name="b" base="0x20" count="1">
+				<reg name="x" offset="0x0" size="word">
+					<alias name="A_X" />
+				</reg>
+			</block>
+		</registry>"##).unwrap();
+
+		assert_eq!(registry.validate(), [
+			Diagnostic::BlockOverlap { first: "a".into(), second: "b".into() },
+			Diagnostic::RegisterOverlap { block: "a".into(), first: "x".into(), second: "y".into() },
+			Diagnostic::OutOfBounds { block: "a".into(), register: "z".into(), offset: 0x40, size: 0x40 },
+			Diagnostic::DuplicateName("a_x".into()),
+		]);
+
+		let registry = parse(r##"<registry>
+			<block name="a" base="0x0" count="100000000" />
+			<block name="b" base="0x40" count="1">
+				<reg name="x" offset="0xffffffff" size="word" />
+			</block>
+		</registry>"##).unwrap();
+
+		assert_eq!(registry.validate(), [
+			Diagnostic::BlockTooLarge("a".into()),
+			Diagnostic::OutOfBounds { block: "b".into(), register: "x".into(), offset: 0xffffffff, size: 0x40 },
+		]);
+
+		// Fits in a u32 but not in the 18 bit CSR address space
+		let registry = parse(r##"<registry>
+			<block name="a" base="0x3ff00" count="3" />
+			<block name="b" base="0x3ffc0" count="4" />
+			<block name="c" base="0x40000" count="1" />
+		</registry>"##).unwrap();
+
+		assert_eq!(registry.validate(), [
+			Diagnostic::BlockTooLarge("b".into()),
+			Diagnostic::BlockTooLarge("c".into()),
+		]);
+
+		let registry = parse(r##"<registry>
+			<block name="a" base="c" count="1" />
+			<block name="b" base="a" count="1" />
+			<block name="c" base="b" count="1" />
+			<block name="d" base="c" count="1" />
+		</registry>"##).unwrap();
+
+		let diagnostics = registry.validate();
+		assert_eq!(diagnostics.iter().filter(|d| matches!(d, Diagnostic::Cycle(_))).count(), 1);
+		assert!(diagnostics.contains(&Diagnostic::Cycle(vec!["a".into(), "c".into(), "b".into()])));
+
+		let registry = parse(r##"<registry>
+			<block name="a" base="0x0" count="1">
+				<reg name="x" offset="0x0" size="short">
+					<field name="lo" lsb="0" width="8" />
+					<field name="mid" lsb="4" width="8" />
+					<field name="hi" lsb="12" width="8" />
+					<field name="LO" lsb="15" width="1" />
+				</reg>
+			</block>
+		</registry>"##).unwrap();
+
+		assert_eq!(registry.validate(), [
+			Diagnostic::FieldOverlap { register: "a_x".into(), first: "lo".into(), second: "mid".into() },
+			Diagnostic::FieldOutOfBounds { register: "a_x".into(), field: "hi".into() },
+			Diagnostic::DuplicateName("a_x.lo".into()),
+		]);
+
+		let duplicate = r##"<registry>
+			<block name="a" base="0x0" count="1" />
+			<block name="a" base="0x40" count="1" />
+		</registry>"##;
+		assert!(matches!(parse(duplicate), Err(Error { kind: ErrorKind::DuplicateBlock(name), .. }) if name == "a"));
+	}
 }
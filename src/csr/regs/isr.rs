@@ -1,41 +0,0 @@
-/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
-use super::CSR_BLOCK_SIZE;
-pub const ISR_BASE: u32 = 0x040;
-pub const ISR_SIZE: u32 = 3 * CSR_BLOCK_SIZE;
-
-pub const ISR_BASE_REG: u32 = 0x040;
-pub const ISR_ERR1_REG: u32 = 0x044;
-pub const ISR_ERR2_REG: u32 = 0x048;
-pub const ISR_ENTER_REG: u32 = 0x04C;
-pub const ISR_EXIT_REG: u32 = 0x050;
-pub const ISR_R1_REG: u32 = 0x080;
-pub const ISR_R2_REG: u32 = 0x084;
-pub const ISR_R3_REG: u32 = 0x088;
-pub const ISR_R4_REG: u32 = 0x08C;
-pub const ISR_R5_REG: u32 = 0x090;
-pub const ISR_R6_REG: u32 = 0x094;
-pub const ISR_R7_REG: u32 = 0x098;
-pub const ISR_R8_REG: u32 = 0x09C;
-pub const ISR_R9_REG: u32 = 0x0A0;
-pub const ISR_R10_REG: u32 = 0x0A4;
-pub const ISR_R11_REG: u32 = 0x0A8;
-pub const ISR_R12_REG: u32 = 0x0AC;
-pub const ISR_R13_REG: u32 = 0x0B0;
-pub const ISR_R14_REG: u32 = 0x0B4;
-pub const ISR_R15_REG: u32 = 0x0B8;
-pub const ISR_R16_REG: u32 = 0x0BC;
-pub const ISR_R17_REG: u32 = 0x0C0;
-pub const ISR_R18_REG: u32 = 0x0C4;
-pub const ISR_R19_REG: u32 = 0x0C8;
-pub const ISR_R20_REG: u32 = 0x0CC;
-pub const ISR_R21_REG: u32 = 0x0D0;
-pub const ISR_R22_REG: u32 = 0x0D4;
-pub const ISR_R23_REG: u32 = 0x0D8;
-pub const ISR_R24_REG: u32 = 0x0DC;
-pub const ISR_R25_REG: u32 = 0x0E0;
-pub const ISR_R26_REG: u32 = 0x0E4;
-pub const ISR_R27_REG: u32 = 0x0E8;
-pub const ISR_SP_REG: u32 = 0x0EC;
-pub const ISR_FP_REG: u32 = 0x0F0;
-pub const ISR_LR_REG: u32 = 0x0F4;
-pub const ISR_PC_REG: u32 = 0x0F8;
@@ -1,10 +1,3 @@
 /* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
-mod dbg_out;
-mod isr;
-mod psr;
-
-pub use dbg_out::*;
-pub use isr::*;
-pub use psr::*;
-
-pub const CSR_BLOCK_SIZE: u32 = 64;
\ No newline at end of file
+// Generated by build.rs from regs.xml, see `Registry::to_rust`
+include!(concat!(env!("OUT_DIR"), "/csr_regs.rs"));
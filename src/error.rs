@@ -0,0 +1,118 @@
+/* Copyright 2023 Robert Zieba, see LICENSE file for full license. */
+use std::fmt;
+
+use crate::Kind;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeErrorKind {
+	/// The top bits don't select any instruction kind
+	InvalidKind,
+	/// A decoder for one instruction kind was given a word of another kind
+	UnexpectedKind(Kind),
+	UnknownBinOp(u32),
+	UnknownCondition(u32),
+	UnknownShiftKind(u32),
+	InvalidWidth(u32),
+	InvalidRegister(u32),
+	ReservedBits,
+}
+
+/// Error produced when a value doesn't decode, `msb` and `lsb` give the offending bits of `value`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+	pub kind: DecodeErrorKind,
+	pub value: u32,
+	pub msb: u8,
+	pub lsb: u8,
+}
+
+impl DecodeError {
+	pub fn new(kind: DecodeErrorKind, value: u32, msb: u8, lsb: u8) -> DecodeError {
+		DecodeError {
+			kind,
+			value,
+			msb,
+			lsb,
+		}
+	}
+
+	/// Moves an error from decoding a field starting at bit `lsb` into the context of the whole word
+	pub fn within(self, value: u32, lsb: u8) -> DecodeError {
+		DecodeError {
+			kind: self.kind,
+			value,
+			msb: self.msb + lsb,
+			lsb: self.lsb + lsb,
+		}
+	}
+}
+
+impl fmt::Display for DecodeError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.kind {
+			DecodeErrorKind::InvalidKind => write!(f, "invalid instruction kind")?,
+			DecodeErrorKind::UnexpectedKind(kind) => write!(f, "unexpected instruction kind {kind:?}")?,
+			DecodeErrorKind::UnknownBinOp(op) => write!(f, "unknown operation {op}")?,
+			DecodeErrorKind::UnknownCondition(cond) => write!(f, "unknown condition {cond}")?,
+			DecodeErrorKind::UnknownShiftKind(kind) => write!(f, "unknown shift kind {kind}")?,
+			DecodeErrorKind::InvalidWidth(width) => write!(f, "invalid width {width}")?,
+			DecodeErrorKind::InvalidRegister(reg) => write!(f, "invalid register {reg}")?,
+			DecodeErrorKind::ReservedBits => write!(f, "reserved bits set")?,
+		}
+		write!(f, " in {:#010x} bits [{}:{}]", self.value, self.msb, self.lsb)
+	}
+}
+
+impl std::error::Error for DecodeError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EncodeError {
+	/// Immediate doesn't fit in a field of `bits` bits
+	ImmediateOutOfRange {
+		value: i64,
+		bits: u8,
+	},
+	ShiftOutOfRange(u8),
+	/// Jump offset isn't a multiple of the instruction size
+	MisalignedOffset(i32),
+}
+
+impl fmt::Display for EncodeError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			EncodeError::ImmediateOutOfRange { value, bits } => write!(f, "immediate {value} does not fit in {bits} bits"),
+			EncodeError::ShiftOutOfRange(shift) => write!(f, "shift amount {shift} is greater than 31"),
+			EncodeError::MisalignedOffset(offset) => write!(f, "offset {offset} is not a multiple of 4"),
+		}
+	}
+}
+
+impl std::error::Error for EncodeError {}
+
+/// Checks that `value` fits in a signed field of `bits` bits
+pub(crate) fn check_signed(value: i64, bits: u8) -> Result<(), EncodeError> {
+	let min = -(1i64 << (bits - 1));
+	let max = (1i64 << (bits - 1)) - 1;
+	if value < min || value > max {
+		Err(EncodeError::ImmediateOutOfRange { value, bits })
+	} else {
+		Ok(())
+	}
+}
+
+/// Checks that `value` fits in an unsigned field of `bits` bits
+pub(crate) fn check_unsigned(value: i64, bits: u8) -> Result<(), EncodeError> {
+	if value < 0 || value >= (1i64 << bits) {
+		Err(EncodeError::ImmediateOutOfRange { value, bits })
+	} else {
+		Ok(())
+	}
+}
+
+pub(crate) fn check_shift(shift: u8) -> Result<(), EncodeError> {
+	if shift > 31 {
+		Err(EncodeError::ShiftOutOfRange(shift))
+	} else {
+		Ok(())
+	}
+}
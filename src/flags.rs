@@ -0,0 +1,75 @@
+/* Copyright 2023 Robert Zieba, see LICENSE file for full license. */
+use crate::csr::regs::*;
+
+/// Condition flags produced by `BinOp::eval`, stored in the `PSR_PSR0_REG` CSR
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Flags {
+	pub overflow: bool,
+	/// Carry out of an addition, or borrow out of a subtraction
+	pub carry: bool,
+	pub zero: bool,
+	pub negative: bool,
+}
+
+impl Flags {
+	pub const OVERFLOW_BIT: u32 = PSR_PSR0_OVERFLOW_LSB;
+	pub const CARRY_BIT: u32 = PSR_PSR0_CARRY_LSB;
+	pub const ZERO_BIT: u32 = PSR_PSR0_ZERO_LSB;
+	pub const NEGATIVE_BIT: u32 = PSR_PSR0_NEGATIVE_LSB;
+	/// Bits of the PSR holding flags
+	pub const PSR_MASK: u32 = PSR_PSR0_OVERFLOW_MASK
+		| PSR_PSR0_CARRY_MASK
+		| PSR_PSR0_ZERO_MASK
+		| PSR_PSR0_NEGATIVE_MASK;
+
+	/// Flags for `result` with the given carry and overflow
+	pub fn from_result(result: u32, carry: bool, overflow: bool) -> Flags {
+		Flags {
+			overflow,
+			carry,
+			zero: result == 0,
+			negative: (result as i32) < 0,
+		}
+	}
+
+	/// Reads the flags out of a PSR value, other bits are ignored
+	pub fn from_psr(psr: u32) -> Flags {
+		let bit = |n: u32| psr & (1 << n) != 0;
+		Flags {
+			overflow: bit(Self::OVERFLOW_BIT),
+			carry: bit(Self::CARRY_BIT),
+			zero: bit(Self::ZERO_BIT),
+			negative: bit(Self::NEGATIVE_BIT),
+		}
+	}
+
+	/// Returns `psr` with the flag bits replaced by these flags
+	pub fn to_psr(&self, psr: u32) -> u32 {
+		let flags = (self.overflow as u32) << Self::OVERFLOW_BIT
+			| (self.carry as u32) << Self::CARRY_BIT
+			| (self.zero as u32) << Self::ZERO_BIT
+			| (self.negative as u32) << Self::NEGATIVE_BIT;
+		(psr & !Self::PSR_MASK) | flags
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn psr() {
+		let flags = Flags {
+			overflow: true,
+			carry: false,
+			zero: true,
+			negative: false,
+		};
+
+		assert_eq!(flags.to_psr(0), 0b0101);
+		assert_eq!(flags.to_psr(0xffff_fff0), 0xffff_fff5);
+		assert_eq!(flags.to_psr(0xffff_ffff), 0xffff_fff5);
+		assert_eq!(Flags::from_psr(0xffff_fff5), flags);
+		assert_eq!(Flags::from_psr(0b1010), Flags { overflow: false, carry: true, zero: false, negative: true });
+	}
+}
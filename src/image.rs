@@ -0,0 +1,631 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use object::{elf, Endianness, Object, ObjectKind, ObjectSection, ObjectSymbol, RelocationFlags, RelocationTarget, SectionKind, SymbolKind};
+use object::read::elf::{ElfFile32, ElfSection32};
+use object::write::elf::{FileHeader, Rel, SectionHeader, Sym, Writer};
+
+use crate::{Encode, Endian, Width, jump};
+use crate::asm::{Program, Relocation, RelocationKind, Symbol};
+
+/// Data bytes per Intel HEX record
+const HEX_RECORD_LEN: usize = 16;
+
+// ELF relocation types, BIBE doesn't have a machine number so these are its own
+pub const R_BIBE_NONE: u32 = 0;
+/// Word holding `S + A`
+pub const R_BIBE_32: u32 = 1;
+pub const R_BIBE_16: u32 = 2;
+pub const R_BIBE_8: u32 = 3;
+/// `j` to `S + A - P`, the addend is the jump's offset field
+pub const R_BIBE_JUMP: u32 = 4;
+
+/// File formats understood by `Image::read`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+	/// Bytes loaded at a given address
+	Raw,
+	IntelHex,
+	/// 32-bit ELF, only code sections are loaded. Written as a relocatable object with a section per segment.
+	Elf,
+}
+
+impl Format {
+	/// Guesses the format from the contents: ELF by its magic number, Intel HEX by its leading `:`
+	pub fn detect(bytes: &[u8]) -> Format {
+		if bytes.starts_with(b"\x7fELF") {
+			Format::Elf
+		} else if bytes.trim_ascii_start().starts_with(b":") && bytes.is_ascii() {
+			Format::IntelHex
+		} else {
+			Format::Raw
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum Error {
+	Hex(ihex::ReaderError),
+	/// Intel HEX that isn't text
+	NotText,
+	Elf(object::Error),
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Error::Hex(err) => write!(f, "invalid Intel HEX: {err}"),
+			Error::NotText => write!(f, "invalid Intel HEX: not ASCII text"),
+			Error::Elf(err) => write!(f, "invalid ELF: {err}"),
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+impl From<ihex::ReaderError> for Error {
+	fn from(err: ihex::ReaderError) -> Self {
+		Error::Hex(err)
+	}
+}
+
+impl From<object::Error> for Error {
+	fn from(err: object::Error) -> Self {
+		Error::Elf(err)
+	}
+}
+
+/// Contiguous bytes starting at `addr`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+	/// Section name for ELF images
+	pub name: Option<String>,
+	pub addr: u32,
+	pub bytes: Vec<u8>,
+}
+
+/// Contents of a program file, independent of its format
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Image {
+	/// Ordered by address
+	pub segments: Vec<Segment>,
+	/// Symbol names by address
+	pub symbols: BTreeMap<u32, String>,
+	/// Symbols visible to other objects when linked
+	pub globals: BTreeSet<String>,
+	/// Fields to patch if the segments are moved, the bytes hold their values at the segment's address
+	pub relocations: Vec<Relocation>,
+	pub entry: Option<u32>,
+	/// Byte order recorded in the file, only ELF has one
+	pub endian: Option<Endian>,
+}
+
+impl Image {
+	/// Reads `bytes` as `format`, `base` is the load address of a raw image
+	pub fn read(bytes: &[u8], format: Format, base: u32) -> Result<Image, Error> {
+		match format {
+			Format::Raw => Ok(Image {
+				segments: vec![Segment {
+					name: None,
+					addr: base,
+					bytes: bytes.to_vec(),
+				}],
+				..Default::default()
+			}),
+			Format::IntelHex => Self::read_hex(std::str::from_utf8(bytes).map_err(|_| Error::NotText)?),
+			Format::Elf => Self::read_elf(bytes),
+		}
+	}
+
+	fn read_hex(text: &str) -> Result<Image, Error> {
+		let mut image = Image::default();
+		let mut upper = 0;
+		for record in ihex::Reader::new(text) {
+			match record? {
+				ihex::Record::Data { offset, value } => {
+					let addr = upper + offset as u32;
+					match image.segments.last_mut() {
+						Some(last) if last.addr.wrapping_add(last.bytes.len() as u32) == addr => {
+							last.bytes.extend_from_slice(&value);
+						},
+						_ => image.segments.push(Segment {
+							name: None,
+							addr,
+							bytes: value,
+						}),
+					}
+				},
+				ihex::Record::ExtendedSegmentAddress(segment) => upper = (segment as u32) << 4,
+				ihex::Record::ExtendedLinearAddress(high) => upper = (high as u32) << 16,
+				ihex::Record::StartLinearAddress(addr) => image.entry = Some(addr),
+				ihex::Record::StartSegmentAddress { cs, ip } => image.entry = Some(((cs as u32) << 4) + ip as u32),
+				ihex::Record::EndOfFile => break,
+			}
+		}
+
+		image.segments.sort_by_key(|s| s.addr);
+		Ok(image)
+	}
+
+	fn read_elf(bytes: &[u8]) -> Result<Image, Error> {
+		let file = ElfFile32::<Endianness>::parse(bytes)?;
+		let mut image = Image {
+			entry: Some(file.entry() as u32).filter(|&entry| entry != 0),
+			endian: Some(if file.is_little_endian() { Endian::Little } else { Endian::Big }),
+			..Default::default()
+		};
+
+		for section in file.sections().filter(|s| s.kind() == SectionKind::Text) {
+			let addr = section.address() as u32;
+			let mut bytes = section.data()?.to_vec();
+			if file.kind() == ObjectKind::Relocatable {
+				Self::resolve_relocations(&file, &section, &mut bytes, &mut image.relocations)?;
+			}
+			image.segments.push(Segment {
+				name: Some(section.name()?.to_string()),
+				addr,
+				bytes,
+			});
+		}
+		image.segments.sort_by_key(|s| s.addr);
+		image.relocations.sort_by_key(|r| r.addr);
+
+		// Assembler labels are untyped and zero sized, which `is_definition` rejects
+		let symbols = file.symbols()
+			.filter(|s| !s.is_undefined() && matches!(s.kind(), SymbolKind::Text | SymbolKind::Label | SymbolKind::Unknown));
+		for symbol in symbols {
+			let (Some(index), name) = (symbol.section_index(), symbol.name()?) else {
+				continue;
+			};
+			// Values in relocatable objects are offsets into their section
+			let mut addr = symbol.address() as u32;
+			if file.kind() == ObjectKind::Relocatable {
+				addr = addr.wrapping_add(file.section_by_index(index)?.address() as u32);
+			}
+			if !name.is_empty() {
+				image.symbols.entry(addr).or_insert_with(|| name.to_string());
+				if symbol.is_global() {
+					image.globals.insert(name.to_string());
+				}
+			}
+		}
+
+		Ok(image)
+	}
+
+	/// Applies the `R_BIBE_*` relocations of `section` to `bytes` for its current address, the reverse of `to_elf`
+	fn resolve_relocations(file: &ElfFile32<Endianness>, section: &ElfSection32<Endianness>, bytes: &mut [u8], relocations: &mut Vec<Relocation>) -> Result<(), Error> {
+		let base = section.address() as u32;
+		for (offset, relocation) in section.relocations() {
+			let RelocationFlags::Elf { r_type } = relocation.flags() else {
+				continue;
+			};
+			let (len, kind) = match r_type {
+				R_BIBE_32 => (4, RelocationKind::Absolute(Width::Word)),
+				R_BIBE_16 => (2, RelocationKind::Absolute(Width::Short)),
+				R_BIBE_8 => (1, RelocationKind::Absolute(Width::Byte)),
+				R_BIBE_JUMP => (4, RelocationKind::Jump),
+				_ => continue,
+			};
+			let symbol = match relocation.target() {
+				RelocationTarget::Symbol(index) => {
+					let symbol = file.symbol_by_index(index)?;
+					let section = symbol.section_index().map(|index| file.section_by_index(index)).transpose()?;
+					section.map_or(0, |s| s.address() as u32).wrapping_add(symbol.address() as u32)
+				},
+				_ => 0,
+			};
+			let Some(field) = bytes.get_mut(offset as usize..offset as usize + len) else {
+				continue;
+			};
+
+			let addr = base.wrapping_add(offset as u32);
+			let mut value = [0; 4];
+			value[..len].copy_from_slice(field);
+			let addend = u32::from_le_bytes(value);
+			let value = match kind {
+				RelocationKind::Absolute(_) => symbol.wrapping_add(addend),
+				RelocationKind::Jump => match jump::Instruction::decode(addend) {
+					Ok(jump) => {
+						let target = symbol.wrapping_add(jump.imm as u32);
+						jump::Instruction { imm: target.wrapping_sub(addr) as i32 }.encode()
+					},
+					Err(_) => continue,
+				},
+			};
+			field.copy_from_slice(&value.to_le_bytes()[..len]);
+			relocations.push(Relocation { addr, kind });
+		}
+		Ok(())
+	}
+
+	/// Segments as one block starting at the lowest address, gaps are filled with zeros
+	pub fn to_raw(&self) -> Vec<u8> {
+		let Some(base) = self.segments.first().map(|s| s.addr) else {
+			return Vec::new();
+		};
+
+		let mut bytes = Vec::new();
+		for segment in &self.segments {
+			let offset = segment.addr.wrapping_sub(base) as usize;
+			if bytes.len() < offset {
+				bytes.resize(offset, 0);
+			}
+			bytes.truncate(offset);
+			bytes.extend_from_slice(&segment.bytes);
+		}
+		bytes
+	}
+
+	pub fn to_intel_hex(&self) -> String {
+		let mut records = Vec::new();
+		let mut upper = None;
+		for segment in &self.segments {
+			for (index, chunk) in segment.bytes.chunks(HEX_RECORD_LEN).enumerate() {
+				let addr = segment.addr.wrapping_add((index * HEX_RECORD_LEN) as u32);
+				let high = (addr >> 16) as u16;
+				if upper != Some(high) {
+					records.push(ihex::Record::ExtendedLinearAddress(high));
+					upper = Some(high);
+				}
+				// A record can't wrap past the end of its 64K region
+				let split = chunk.len().min(0x10000 - (addr & 0xffff) as usize);
+				records.push(ihex::Record::Data { offset: addr as u16, value: chunk[..split].to_vec() });
+				if split < chunk.len() {
+					let high = high.wrapping_add(1);
+					records.push(ihex::Record::ExtendedLinearAddress(high));
+					upper = Some(high);
+					records.push(ihex::Record::Data { offset: 0, value: chunk[split..].to_vec() });
+				}
+			}
+		}
+		if let Some(entry) = self.entry {
+			records.push(ihex::Record::StartLinearAddress(entry));
+		}
+		records.push(ihex::Record::EndOfFile);
+
+		// Records are built within the format's limits
+		ihex::create_object_file_representation(&records).unwrap()
+	}
+
+	/// Index of the segment containing `addr`, or ending at it like a label after the last byte
+	fn segment_at(&self, addr: u32) -> Option<usize> {
+		self.segments.iter().position(|s| addr.wrapping_sub(s.addr) <= s.bytes.len() as u32)
+	}
+
+	/// Little endian relocatable ELF, a section per segment at address 0 for the linker to place
+	///
+	/// Symbols are offsets into their section and are global if named in `globals`. Relocations
+	/// are `R_BIBE_*` with implicit addends, the fields hold the value they'd have if the section
+	/// stayed at 0. There's no machine number assigned to BIBE, `e_machine` is `EM_NONE`.
+	pub fn to_elf(&self) -> Vec<u8> {
+		let names: Vec<&str> = self.segments.iter().map(|s| s.name.as_deref().unwrap_or(".text")).collect();
+		let rel_names: Vec<String> = names.iter().map(|name| format!(".rel{name}")).collect();
+
+		let mut contents: Vec<Vec<u8>> = self.segments.iter().map(|s| s.bytes.clone()).collect();
+		let mut relocations: Vec<Vec<Rel>> = vec![Vec::new(); self.segments.len()];
+		for relocation in &self.relocations {
+			let Some(index) = self.segment_at(relocation.addr) else {
+				continue;
+			};
+			let offset = relocation.addr.wrapping_sub(self.segments[index].addr) as usize;
+			let (len, r_type) = match relocation.kind {
+				RelocationKind::Absolute(Width::Word) => (4, R_BIBE_32),
+				RelocationKind::Absolute(Width::Short) => (2, R_BIBE_16),
+				RelocationKind::Absolute(Width::Byte) => (1, R_BIBE_8),
+				RelocationKind::Jump => (4, R_BIBE_JUMP),
+			};
+			let Some(field) = contents[index].get_mut(offset..offset + len) else {
+				continue;
+			};
+
+			let mut bytes = [0; 4];
+			bytes[..len].copy_from_slice(field);
+			let value = u32::from_le_bytes(bytes);
+			let (r_sym, addend) = match relocation.kind {
+				// Relative to the section holding the address, its symbol follows the null symbol
+				RelocationKind::Absolute(_) => match self.segment_at(value) {
+					Some(target) => (target as u32 + 1, value.wrapping_sub(self.segments[target].addr)),
+					None => (0, value),
+				},
+				// `S + A - P` with no symbol, the field holds the target instead of the offset to it
+				RelocationKind::Jump => match jump::Instruction::decode(value) {
+					Ok(jump) => (0, jump::Instruction { imm: jump.target(relocation.addr) as i32 }.encode()),
+					Err(_) => continue,
+				},
+			};
+			field.copy_from_slice(&addend.to_le_bytes()[..len]);
+			relocations[index].push(Rel {
+				r_offset: offset as u64,
+				r_sym,
+				r_type,
+				r_addend: 0,
+			});
+		}
+
+		let mut buffer = Vec::new();
+		let mut writer = Writer::new(Endianness::Little, false, &mut buffer);
+
+		writer.reserve_file_header();
+		writer.reserve_null_section_index();
+		let sections: Vec<_> = contents.iter().zip(&names).map(|(bytes, name)| {
+			let name = writer.add_section_name(name.as_bytes());
+			let index = writer.reserve_section_index();
+			let offset = writer.reserve(bytes.len(), 4);
+			(name, index, offset)
+		}).collect();
+		let rel_sections: Vec<_> = relocations.iter().zip(&rel_names).map(|(rels, name)| {
+			(!rels.is_empty()).then(|| {
+				let name = writer.add_section_name(name.as_bytes());
+				writer.reserve_section_index();
+				(name, writer.reserve_relocations(rels.len(), false))
+			})
+		}).collect();
+
+		writer.reserve_null_symbol_index();
+		for &(_, index, _) in &sections {
+			writer.reserve_symbol_index(Some(index));
+		}
+		// Locals have to come before globals
+		let mut symbols: Vec<_> = self.symbols.iter().filter_map(|(&addr, name)| {
+			let section = self.segment_at(addr)?;
+			Some((self.globals.contains(name), name, section, addr.wrapping_sub(self.segments[section].addr)))
+		}).collect();
+		symbols.sort_by_key(|&(global, ..)| global);
+		let symbols: Vec<_> = symbols.into_iter().map(|(global, name, section, value)| {
+			writer.reserve_symbol_index(Some(sections[section].1));
+			(writer.add_string(name.as_bytes()), global, section, value)
+		}).collect();
+		let locals = 1 + sections.len() + symbols.iter().filter(|&&(_, global, ..)| !global).count();
+		writer.reserve_symtab_section_index();
+		writer.reserve_symtab();
+		writer.reserve_strtab_section_index();
+		writer.reserve_strtab();
+		writer.reserve_shstrtab_section_index();
+		writer.reserve_shstrtab();
+		writer.reserve_section_headers();
+
+		// The header only fails to write for 64-bit fields in a 32-bit file
+		writer.write_file_header(&FileHeader {
+			os_abi: elf::ELFOSABI_NONE,
+			abi_version: 0,
+			e_type: elf::ET_REL,
+			e_machine: elf::EM_NONE,
+			e_entry: 0,
+			e_flags: 0,
+		}).unwrap();
+		for bytes in &contents {
+			writer.write_align(4);
+			writer.write(bytes);
+		}
+		for rels in relocations.iter().filter(|rels| !rels.is_empty()) {
+			writer.write_align_relocation();
+			for rel in rels {
+				writer.write_relocation(false, rel);
+			}
+		}
+
+		writer.write_null_symbol();
+		for &(_, index, _) in &sections {
+			writer.write_symbol(&Sym {
+				name: None,
+				section: Some(index),
+				st_info: (elf::STB_LOCAL << 4) | elf::STT_SECTION,
+				st_other: elf::STV_DEFAULT,
+				st_shndx: 0,
+				st_value: 0,
+				st_size: 0,
+			});
+		}
+		for &(name, global, section, value) in &symbols {
+			let bind = if global { elf::STB_GLOBAL } else { elf::STB_LOCAL };
+			writer.write_symbol(&Sym {
+				name: Some(name),
+				section: Some(sections[section].1),
+				st_info: (bind << 4) | elf::STT_NOTYPE,
+				st_other: elf::STV_DEFAULT,
+				st_shndx: 0,
+				st_value: value as u64,
+				st_size: 0,
+			});
+		}
+		writer.write_strtab();
+		writer.write_shstrtab();
+
+		writer.write_null_section_header();
+		for (bytes, &(name, _, offset)) in contents.iter().zip(&sections) {
+			writer.write_section_header(&SectionHeader {
+				name: Some(name),
+				sh_type: elf::SHT_PROGBITS,
+				sh_flags: (elf::SHF_ALLOC | elf::SHF_EXECINSTR) as u64,
+				sh_addr: 0,
+				sh_offset: offset as u64,
+				sh_size: bytes.len() as u64,
+				sh_link: 0,
+				sh_info: 0,
+				sh_addralign: 4,
+				sh_entsize: 0,
+			});
+		}
+		for ((rels, &(_, index, _)), rel_section) in relocations.iter().zip(&sections).zip(&rel_sections) {
+			if let &Some((name, offset)) = rel_section {
+				let symtab = writer.symtab_index();
+				writer.write_relocation_section_header(name, index, symtab, offset, rels.len(), false);
+			}
+		}
+		writer.write_symtab_section_header(locals as u32);
+		writer.write_strtab_section_header();
+		writer.write_shstrtab_section_header();
+
+		buffer
+	}
+}
+
+/// Assembled program as a single segment with its labels as symbols
+impl From<&Program> for Image {
+	fn from(program: &Program) -> Self {
+		Image {
+			segments: vec![Segment {
+				name: None,
+				addr: program.origin,
+				bytes: program.bytes(),
+			}],
+			symbols: program.symbols.iter()
+				.filter_map(|(name, symbol)| match symbol {
+					Symbol::Label(addr) => Some((*addr, name.clone())),
+					Symbol::Constant(_) => None,
+				})
+				.collect(),
+			globals: program.globals.clone(),
+			relocations: program.relocations.clone(),
+			..Default::default()
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn detect() {
+		assert_eq!(Format::detect(b"\x7fELF\x01\x01"), Format::Elf);
+		assert_eq!(Format::detect(b"\n:0400000001020304F2\n"), Format::IntelHex);
+		assert_eq!(Format::detect(b":\xff"), Format::Raw);
+		assert_eq!(Format::detect(&[0x0c, 0x00, 0x30, 0x48]), Format::Raw);
+	}
+
+	#[test]
+	fn hex() {
+		let text = "\
+			:020000040001F9\n\
+			:0400000001020304F2\n\
+			:020004000506EF\n\
+			:02001000AABB89\n\
+			:0400000500010004F2\n\
+			:00000001FF\n";
+		let image = Image::read(text.as_bytes(), Format::IntelHex, 0).unwrap();
+
+		assert_eq!(image.segments, [
+			Segment { name: None, addr: 0x10000, bytes: vec![1, 2, 3, 4, 5, 6] },
+			Segment { name: None, addr: 0x10010, bytes: vec![0xaa, 0xbb] },
+		]);
+		assert_eq!(image.entry, Some(0x10004));
+		assert_eq!(image.endian, None);
+
+		assert!(matches!(Image::read(b":0400", Format::IntelHex, 0), Err(Error::Hex(_))));
+	}
+
+	#[test]
+	fn raw() {
+		let image = Image::read(&[1, 2, 3], Format::Raw, 0x100).unwrap();
+		assert_eq!(image.segments, [Segment { name: None, addr: 0x100, bytes: vec![1, 2, 3] }]);
+		assert!(Image::read(b"\x7fELF", Format::Elf, 0).is_err());
+	}
+
+	#[test]
+	fn round_trip() {
+		let program = crate::asm::Assembler::new().assemble("
+			.org 0xfff8
+		start:
+			add r1, r0, 1
+		loop:
+			j loop
+			.word 1, 2
+			.byte 3
+		").unwrap();
+		let mut image = Image::from(&program);
+		assert_eq!(image.symbols, BTreeMap::from([(0xfff8, "start".into()), (0xfffc, "loop".into())]));
+
+		let bytes = image.to_elf();
+		let file = ElfFile32::<Endianness>::parse(&bytes[..]).unwrap();
+		assert_eq!(file.kind(), ObjectKind::Relocatable);
+
+		// Object file sections start at 0
+		let elf = Image::read(&bytes, Format::Elf, 0).unwrap();
+		assert_eq!(elf.segments, [Segment { name: Some(".text".into()), addr: 0, bytes: image.segments[0].bytes.clone() }]);
+		assert_eq!(elf.symbols, BTreeMap::from([(0, "start".into()), (4, "loop".into())]));
+		assert_eq!(elf.entry, None);
+		assert_eq!(elf.endian, Some(Endian::Little));
+
+		// Crosses a 64K boundary mid-record
+		image.entry = Some(0xfff8);
+		let hex = Image::read(image.to_intel_hex().as_bytes(), Format::IntelHex, 0).unwrap();
+		assert_eq!(hex.segments, image.segments);
+		assert_eq!(hex.entry, image.entry);
+
+		image.segments.push(Segment { name: None, addr: 0x10010, bytes: vec![9] });
+		let raw = image.to_raw();
+		assert_eq!(raw.len(), 0x19);
+		assert_eq!(raw[..0x14], program.bytes()[..]);
+		assert_eq!(raw[0x14..0x18], [0; 4]);
+		assert_eq!(raw[0x18], 9);
+	}
+
+	#[test]
+	fn relocatable() {
+		let source = "
+			.global start, table
+		start:
+			call func
+			j 0x40
+		func:
+			ret
+		table:
+			.word func, end - start, 7
+			.half table
+		end:
+		";
+		let program = crate::asm::Assembler::new().assemble(&format!(".org 0x100\n{source}")).unwrap();
+		assert_eq!(program.relocations, [
+			Relocation { addr: 0x108, kind: RelocationKind::Jump },
+			Relocation { addr: 0x110, kind: RelocationKind::Absolute(Width::Word) },
+			Relocation { addr: 0x11c, kind: RelocationKind::Absolute(Width::Short) },
+		]);
+
+		let bytes = Image::from(&program).to_elf();
+		let file = ElfFile32::<Endianness>::parse(&bytes[..]).unwrap();
+		assert_eq!(file.kind(), ObjectKind::Relocatable);
+		let text = file.section_by_name(".text").unwrap();
+		assert_eq!(text.address(), 0);
+
+		// Fields hold their values for the section at 0, jumps to constants hold the target
+		let data = text.data().unwrap();
+		let word = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+		assert_eq!(word(0x8), jump::Instruction { imm: 0x40 }.encode());
+		assert_eq!(word(0x10), 0xc);
+		assert_eq!(word(0x14), 0x1e);
+		assert_eq!(data[0x1c..0x1e], [0x10, 0]);
+
+		let relocations: Vec<_> = text.relocations().map(|(offset, relocation)| {
+			let RelocationFlags::Elf { r_type } = relocation.flags() else { unreachable!() };
+			let symbol = match relocation.target() {
+				RelocationTarget::Symbol(index) => file.symbol_by_index(index).unwrap().kind(),
+				_ => SymbolKind::Unknown,
+			};
+			(offset, r_type, symbol)
+		}).collect();
+		assert_eq!(relocations, [
+			(0x8, R_BIBE_JUMP, SymbolKind::Unknown),
+			(0x10, R_BIBE_32, SymbolKind::Section),
+			(0x1c, R_BIBE_16, SymbolKind::Section),
+		]);
+
+		let symbol = |name| {
+			let symbol = file.symbol_by_name(name).unwrap();
+			(symbol.address(), symbol.is_global())
+		};
+		assert_eq!(symbol("start"), (0, true));
+		assert_eq!(symbol("table"), (0x10, true));
+		assert_eq!(symbol("func"), (0xc, false));
+		assert_eq!(symbol("end"), (0x1e, false));
+
+		// Reading resolves the relocations for address 0, as if assembled there
+		let elf = Image::read(&bytes, Format::Elf, 0).unwrap();
+		let at_zero = crate::asm::Assembler::new().assemble(source).unwrap();
+		assert_eq!(elf.segments[0].bytes, at_zero.bytes()[..0x20]);
+		assert_eq!(elf.relocations, at_zero.relocations);
+		assert_eq!(elf.globals, BTreeSet::from(["start".into(), "table".into()]));
+	}
+}
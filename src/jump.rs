@@ -1,10 +1,20 @@
+use std::fmt;
+
 use bitfield::bitfield;
 
 use crate::{
+	DecodeError,
+	DecodeErrorKind,
 	Encode,
+	EncodeError,
 	Kind,
+	Register,
+	rri,
 };
 
+/// Bytes from the start of a `call` to the instruction it returns to
+pub const CALL_LEN: u32 = 8;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Instruction {
 	pub imm: i32
@@ -18,9 +28,16 @@ bitfield! {
 }
 
 impl Encode for Instruction {
-	fn decode(value: u32) -> Option<Self> {
+	fn decode(value: u32) -> Result<Self, DecodeError> {
 		let bitfield = Bitfield(value);
-		Some(Instruction {
+		let kind = Kind::decode(value)?;
+
+		if kind != Kind::Jump {
+			return Err(DecodeError::new(DecodeErrorKind::UnexpectedKind(kind), value, 31, 30));
+		}
+
+		// The field holds bits 31:2 of the offset, shifting it into place also restores the sign
+		Ok(Instruction {
 			imm: (bitfield.imm() << 2) as i32,
 		})
 	}
@@ -30,4 +47,93 @@ impl Encode for Instruction {
 		bitfield.set_imm((self.imm as u32) >> 2);
 		bitfield.0
 	}
-}
\ No newline at end of file
+
+	fn try_encode(&self) -> Result<u32, EncodeError> {
+		if self.imm % 4 != 0 {
+			return Err(EncodeError::MisalignedOffset(self.imm));
+		}
+
+		Ok(self.encode())
+	}
+}
+
+impl Instruction {
+	/// Address jumped to when executed from `pc`
+	pub fn target(&self, pc: u32) -> u32 {
+		pc.wrapping_add(self.imm as u32)
+	}
+
+	/// Jump from `pc` to `target`, any aligned target is reachable since addresses wrap
+	pub fn from_target(pc: u32, target: u32) -> Result<Instruction, EncodeError> {
+		let imm = target.wrapping_sub(pc) as i32;
+		if imm % 4 != 0 {
+			return Err(EncodeError::MisalignedOffset(imm));
+		}
+
+		Ok(Instruction {
+			imm,
+		})
+	}
+}
+
+/// Call from `pc` to `target`, linking the address `CALL_LEN` bytes after `pc` into `lr`
+///
+/// Expands to `add lr, pc, 4` and a jump, the PC already points past the `add` when it executes.
+pub fn call(pc: u32, target: u32) -> Result<[crate::Instruction; 2], EncodeError> {
+	let link = rri::Instruction {
+		op: crate::BinOp::Add,
+		cond: rri::Condition::Always,
+		dest: Register::lr(),
+		src: Register::pc(),
+		imm: 4,
+	};
+	let jump = Instruction::from_target(pc.wrapping_add(4), target)?;
+	Ok([crate::Instruction::Rri(link), crate::Instruction::Jump(jump)])
+}
+
+impl fmt::Display for Instruction {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		// Relative to the instruction, since the operand is parsed as a target address
+		write!(f, "j .{:+}", self.imm)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn negative_offsets() {
+		for imm in [-4, -8, -0x1000, i32::MIN, i32::MAX - 3, 0, 4] {
+			let jump = Instruction { imm };
+			let value = jump.try_encode().unwrap();
+			assert_eq!(Instruction::decode(value), Ok(jump), "{imm}");
+		}
+		assert_eq!(Instruction::decode(0xffff_ffff), Ok(Instruction { imm: -4 }));
+		assert_eq!(Instruction::decode(0xe000_0000), Ok(Instruction { imm: i32::MIN }));
+	}
+
+	#[test]
+	fn targets() {
+		let back = Instruction::from_target(0x100, 0xf0).unwrap();
+		assert_eq!(back, Instruction { imm: -0x10 });
+		assert_eq!(back.target(0x100), 0xf0);
+		assert_eq!(Instruction::decode(back.encode()).unwrap().target(0x100), 0xf0);
+
+		// Wraps around either end of the address space
+		let wrap = Instruction::from_target(0x8, 0xffff_fff8).unwrap();
+		assert_eq!(wrap.imm, -0x10);
+		assert_eq!(Instruction::from_target(0xffff_fffc, 0x4).unwrap().imm, 8);
+		assert_eq!(Instruction::from_target(0x0, 0x8000_0000).unwrap().target(0), 0x8000_0000);
+
+		assert_eq!(Instruction::from_target(0x100, 0x102), Err(EncodeError::MisalignedOffset(2)));
+	}
+
+	#[test]
+	fn calls() {
+		let [link, jump] = call(0x100, 0x40).unwrap();
+		assert_eq!(link.to_string(), "add r30, r31, 4");
+		assert_eq!(jump.to_string(), "j .-196");
+		assert!(call(0x100, 0x41).is_err());
+	}
+}
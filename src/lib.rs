@@ -1,8 +1,11 @@
 /* Copyright 2023 Robert Zieba, see LICENSE file for full license. */
+use std::fmt;
+use std::str::FromStr;
+
 use bitfield::bitfield;
 use log::debug;
 use num_derive::{ FromPrimitive, ToPrimitive };
-use num_traits::{ FromPrimitive, ToPrimitive };
+use num_traits::FromPrimitive;
 
 pub mod asm;
 pub mod misc;
@@ -12,21 +15,35 @@ pub mod rri;
 pub mod csr;
 pub mod util;
 pub mod jump;
+pub mod sim;
+#[cfg(feature = "tools")]
+pub mod image;
 
+mod error;
+mod flags;
 mod register;
 mod shift;
+mod stream;
 
+pub use error::*;
+pub use flags::Flags;
 pub use register::*;
 
 pub use rri::Condition;
-pub use util::Width;
+pub use stream::{decode_stream, DecodeStream};
+pub use util::{Endian, Width};
 
 pub use shift::Kind as ShiftKind;
 pub use shift::Shift;
 
 pub trait Encode where Self: Sized {
-	fn decode(value: u32) -> Option<Self>;
+	fn decode(value: u32) -> Result<Self, DecodeError>;
 	fn encode(&self) -> u32;
+
+	/// Encodes, rejecting fields that would otherwise be silently truncated
+	fn try_encode(&self) -> Result<u32, EncodeError> {
+		Ok(self.encode())
+	}
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive, ToPrimitive)]
@@ -57,22 +74,98 @@ pub enum BinOp {
 }
 
 impl Encode for BinOp {
-	fn decode(value: u32) -> Option<BinOp> {
+	fn decode(value: u32) -> Result<BinOp, DecodeError> {
 		BinOp::from_u32(value)
+			.ok_or(DecodeError::new(DecodeErrorKind::UnknownBinOp(value), value, 4, 0))
 	}
 
 	fn encode(&self) -> u32 {
-		self.to_u32().unwrap()
+		*self as u32
 	}
 }
 
 impl BinOp {
 	pub fn is_cc(&self) -> bool {
-		match self {
-			BinOp::Addcc
-			| BinOp::Subcc => true,
-			_ => false,
-		}
+		matches!(self, BinOp::Addcc | BinOp::Subcc)
+	}
+
+	/// Computes `lhs op rhs`
+	///
+	/// Flags are returned for every operation but only `Addcc` and `Subcc` update the PSR.
+	/// `Addcc` adds `carry_in`, `Subcc` subtracts it as a borrow and reports a borrow out
+	/// through the carry flag. Shifts and rotates use the low 5 bits of `rhs`. `Div` and `Mod`
+	/// are signed, dividing by zero gives all ones and a remainder equal to `lhs`. `Not` and
+	/// `Neg` ignore `rhs`.
+	pub fn eval(&self, lhs: u32, rhs: u32, carry_in: bool) -> (u32, Flags) {
+		let amount = rhs & 0x1f;
+		let (result, carry, overflow) = match self {
+			BinOp::Add => (lhs.wrapping_add(rhs), false, false),
+			BinOp::Sub => (lhs.wrapping_sub(rhs), false, false),
+
+			BinOp::Mul => (lhs.wrapping_mul(rhs), false, false),
+			BinOp::Div => match rhs {
+				0 => (u32::MAX, false, false),
+				_ => ((lhs as i32).wrapping_div(rhs as i32) as u32, false, false),
+			},
+			BinOp::Mod => match rhs {
+				0 => (lhs, false, false),
+				_ => ((lhs as i32).wrapping_rem(rhs as i32) as u32, false, false),
+			},
+
+			BinOp::And => (lhs & rhs, false, false),
+			BinOp::Or => (lhs | rhs, false, false),
+			BinOp::Xor => (lhs ^ rhs, false, false),
+
+			BinOp::Shl | BinOp::Asl => (lhs << amount, false, false),
+			BinOp::Shr => (lhs >> amount, false, false),
+			BinOp::Asr => (((lhs as i32) >> amount) as u32, false, false),
+			BinOp::Rol => (lhs.rotate_left(amount), false, false),
+			BinOp::Ror => (lhs.rotate_right(amount), false, false),
+
+			BinOp::Not => (!lhs, false, false),
+			BinOp::Neg => (lhs.wrapping_neg(), false, false),
+
+			BinOp::Addcc => {
+				let wide = lhs as u64 + rhs as u64 + carry_in as u64;
+				let result = wide as u32;
+				let overflow = ((lhs ^ result) & (rhs ^ result)) >> 31 != 0;
+				(result, wide > u32::MAX as u64, overflow)
+			},
+			BinOp::Subcc => {
+				let borrow = (lhs as u64) < rhs as u64 + carry_in as u64;
+				let result = lhs.wrapping_sub(rhs).wrapping_sub(carry_in as u32);
+				let overflow = ((lhs ^ rhs) & (lhs ^ result)) >> 31 != 0;
+				(result, borrow, overflow)
+			},
+		};
+
+		(result, Flags::from_result(result, carry, overflow))
+	}
+}
+
+impl fmt::Display for BinOp {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let mnemonic = match self {
+			BinOp::Add => "add",
+			BinOp::Sub => "sub",
+			BinOp::Mul => "mul",
+			BinOp::Div => "div",
+			BinOp::Mod => "mod",
+			BinOp::And => "and",
+			BinOp::Or => "or",
+			BinOp::Xor => "xor",
+			BinOp::Shl => "shl",
+			BinOp::Shr => "shr",
+			BinOp::Asl => "asl",
+			BinOp::Asr => "asr",
+			BinOp::Rol => "rol",
+			BinOp::Ror => "ror",
+			BinOp::Not => "not",
+			BinOp::Neg => "neg",
+			BinOp::Addcc => "addcc",
+			BinOp::Subcc => "subcc",
+		};
+		f.write_str(mnemonic)
 	}
 }
 
@@ -82,6 +175,15 @@ pub enum LoadStore {
 	Store,
 }
 
+impl fmt::Display for LoadStore {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			LoadStore::Load => f.write_str("ld"),
+			LoadStore::Store => f.write_str("st"),
+		}
+	}
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct LoadStoreOp {
 	pub op: LoadStore,
@@ -99,18 +201,23 @@ impl LoadStoreOp {
 }
 
 impl Encode for LoadStoreOp {
-	fn decode(value: u32) -> Option<Self> {
+	fn decode(value: u32) -> Result<Self, DecodeError> {
+		if value & !0x7 != 0 {
+			return Err(DecodeError::new(DecodeErrorKind::ReservedBits, value, 3, 3));
+		}
+
 		let op = if value & 0x4 == 0 { LoadStore::Load } else { LoadStore::Store };
-		let width = Width::from_u32(value & 0x3)?;
+		let width = Width::from_u32(value & 0x3)
+			.ok_or(DecodeError::new(DecodeErrorKind::InvalidWidth(value & 0x3), value, 1, 0))?;
 
-		Some(LoadStoreOp {
+		Ok(LoadStoreOp {
 			op,
 			width,
 		})
 	}
 
 	fn encode(&self) -> u32 {
-		let encoded = self.width.to_u32().unwrap();
+		let encoded = self.width as u32;
 		match self.op {
 			LoadStore::Load => encoded,
 			LoadStore::Store => encoded | 0x4,
@@ -118,6 +225,12 @@ impl Encode for LoadStoreOp {
 	}
 }
 
+impl fmt::Display for LoadStoreOp {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}.{}", self.op, self.width)
+	}
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Instruction {
 	Memory(memory::Instruction),
@@ -162,24 +275,27 @@ const RI_MEM_HIGH: u32 = 0x2;
 const JUMP_HIGH: u32 = 0x3;
 
 impl Encode for Kind {
-	fn decode(value: u32) -> Option<Self> {
+	fn decode(value: u32) -> Result<Self, DecodeError> {
 		let bitfield = KindBitfield(value);
+		let invalid = DecodeError::new(DecodeErrorKind::InvalidKind, value, 31, 27);
 		match bitfield.high() {
 			RR_HIGH => match bitfield.sub() {
-				RR_RRR_SUB => Some(Kind::Rrr),
+				RR_RRR_SUB => Ok(Kind::Rrr),
 				RR_MEM_CSR_SUB => {
 					match bitfield.discrim() {
-						RR_MEM_DISCRIM => Some(Kind::MemoryRr),
-						RR_CSR_DISCRIM => Some(Kind::Csr),
-						_ => None,
+						RR_MEM_DISCRIM => Ok(Kind::MemoryRr),
+						RR_CSR_DISCRIM => Ok(Kind::Csr),
+						_ => Err(invalid),
 					}
 				},
-				_ => None, 
+				RESERVED0010_SUB => Ok(Kind::Reserved0010),
+				RESERVED0011_SUB => Ok(Kind::Reserved0011),
+				_ => Err(invalid),
 			},
-			RRI_HIGH => Some(Kind::Rri),
-			RI_MEM_HIGH => Some(Kind::MemoryRi),
-			JUMP_HIGH => Some(Kind::Jump),
-			_ => None,
+			RRI_HIGH => Ok(Kind::Rri),
+			RI_MEM_HIGH => Ok(Kind::MemoryRi),
+			JUMP_HIGH => Ok(Kind::Jump),
+			_ => Err(invalid),
 		}
 	}
 
@@ -210,10 +326,12 @@ impl Encode for Kind {
 				bitfield.set_high(JUMP_HIGH);
 			},
 			Kind::Reserved0010 => {
-				bitfield.set_high(RESERVED0010_SUB);
+				bitfield.set_high(RR_HIGH);
+				bitfield.set_sub(RESERVED0010_SUB);
 			},
 			Kind::Reserved0011 => {
-				bitfield.set_high(RESERVED0011_SUB);
+				bitfield.set_high(RR_HIGH);
+				bitfield.set_sub(RESERVED0011_SUB);
 			}
 		};
 
@@ -222,25 +340,20 @@ impl Encode for Kind {
 }
 
 impl Encode for Instruction {
-	fn decode(value: u32) -> Option<Self> {
-		let kind_res = Kind::decode(value);
-		if kind_res.is_none() {
-			debug!("Invalid instruction kind");
-			return None;
-		}
-
-		debug!("Kind {:?}", kind_res.unwrap());
-
-		match kind_res.unwrap() {
-			Kind::MemoryRr => Some(Instruction::Memory(memory::Instruction::decode(value)?)),
-			Kind::MemoryRi => Some(Instruction::Memory(memory::Instruction::decode(value)?)),
-			Kind::Csr => Some(Instruction::Csr(csr::Instruction::decode(value)?)),
-			Kind::Rrr => Some(Instruction::Rrr(rrr::Instruction::decode(value)?)),
-			Kind::Rri => Some(Instruction::Rri(rri::Instruction::decode(value)?)),
-			Kind::Jump => Some(Instruction::Jump(jump::Instruction::decode(value)?)),
-
-			Kind::Reserved0010 => Some(Instruction::Reserved0010(misc::Reserved0010::decode(value)?)),
-			Kind::Reserved0011 => Some(Instruction::Reserved0011(misc::Reserved0011::decode(value)?))
+	fn decode(value: u32) -> Result<Self, DecodeError> {
+		let kind = Kind::decode(value)?;
+		debug!("Kind {:?}", kind);
+
+		match kind {
+			Kind::MemoryRr => Ok(Instruction::Memory(memory::Instruction::decode(value)?)),
+			Kind::MemoryRi => Ok(Instruction::Memory(memory::Instruction::decode(value)?)),
+			Kind::Csr => Ok(Instruction::Csr(csr::Instruction::decode(value)?)),
+			Kind::Rrr => Ok(Instruction::Rrr(rrr::Instruction::decode(value)?)),
+			Kind::Rri => Ok(Instruction::Rri(rri::Instruction::decode(value)?)),
+			Kind::Jump => Ok(Instruction::Jump(jump::Instruction::decode(value)?)),
+
+			Kind::Reserved0010 => Ok(Instruction::Reserved0010(misc::Reserved0010::decode(value)?)),
+			Kind::Reserved0011 => Ok(Instruction::Reserved0011(misc::Reserved0011::decode(value)?))
 		}
 	}
 
@@ -256,6 +369,106 @@ impl Encode for Instruction {
 			Instruction::Reserved0011(i) => i.encode(),
 		}
 	}
+
+	fn try_encode(&self) -> Result<u32, EncodeError> {
+		match self {
+			Instruction::Memory(i) => i.try_encode(),
+			Instruction::Csr(i) => i.try_encode(),
+			Instruction::Rrr(i) => i.try_encode(),
+			Instruction::Rri(i) => i.try_encode(),
+			Instruction::Jump(i) => i.try_encode(),
+
+			Instruction::Reserved0010(i) => i.try_encode(),
+			Instruction::Reserved0011(i) => i.try_encode(),
+		}
+	}
+}
+
+impl fmt::Display for Instruction {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Instruction::Memory(i) => i.fmt(f),
+			Instruction::Csr(i) => i.fmt(f),
+			Instruction::Rrr(i) => i.fmt(f),
+			Instruction::Rri(i) => i.fmt(f),
+			Instruction::Jump(i) => i.fmt(f),
+
+			Instruction::Reserved0010(i) => i.fmt(f),
+			Instruction::Reserved0011(i) => i.fmt(f),
+		}
+	}
+}
+
+impl Instruction {
+	/// Displays CSR addresses by name when `registry` describes them
+	pub fn display_with<'a>(&'a self, registry: &'a csr::registry::Registry) -> csr::Symbolic<'a, Self> {
+		csr::Symbolic::new(self, registry)
+	}
+
+	/// Whether the instruction can change the PC other than by advancing it
+	pub fn writes_pc(&self) -> bool {
+		let pc = Register::pc();
+		match self {
+			Instruction::Memory(memory::Instruction::Rr(i)) => i.op.is_load() && i.rd == pc,
+			Instruction::Memory(memory::Instruction::Ri(i)) => i.op.is_load() && i.rd == pc,
+			Instruction::Csr(i) => i.op.is_load() && i.reg == pc,
+			Instruction::Rrr(i) => i.dest == pc,
+			Instruction::Rri(i) => i.dest == pc,
+			Instruction::Jump(_) => true,
+			Instruction::Reserved0010(_)
+			| Instruction::Reserved0011(_) => false,
+		}
+	}
+
+	/// Whether this is `ret`, an `add` copying `lr` into the PC, possibly conditional
+	pub fn is_return(&self) -> bool {
+		let (pc, lr, z) = (Register::pc(), Register::lr(), Register::z());
+		match self {
+			Instruction::Rri(i) => i.op == BinOp::Add && i.dest == pc && i.src == lr && i.imm == 0,
+			Instruction::Rrr(i) => i.op == BinOp::Add && i.dest == pc
+				&& ((i.lhs == lr && i.rhs == z) || (i.lhs == z && i.rhs == lr && i.shift.shift == 0)),
+			_ => false,
+		}
+	}
+
+	/// Whether this is `add rd, pc, 4`, saving a return address before `call` or `jalr` jumps
+	pub fn is_link(&self) -> bool {
+		matches!(self, Instruction::Rri(i) if i.op == BinOp::Add
+			&& i.cond == Condition::Always
+			&& i.src == Register::pc()
+			&& i.imm == 4
+			&& i.dest != Register::pc())
+	}
+
+	/// Address the instruction at `pc` branches to, if it's known without running it
+	///
+	/// That's jumps and `add`s writing the PC from itself or `r0`, indirect branches such as
+	/// `jr` and `ret` give `None`. Conditional branches give the address taken when they're taken.
+	pub fn branch_target(&self, pc: u32) -> Option<u32> {
+		match self {
+			Instruction::Jump(i) => Some(i.target(pc)),
+			Instruction::Rri(i) if i.op == BinOp::Add && i.dest == Register::pc() => {
+				let imm = i.imm as i32 as u32;
+				if i.src == Register::pc() {
+					// The PC already points at the next instruction
+					Some(pc.wrapping_add(4).wrapping_add(imm))
+				} else if i.src == Register::z() {
+					Some(imm)
+				} else {
+					None
+				}
+			},
+			_ => None,
+		}
+	}
+}
+
+impl FromStr for Instruction {
+	type Err = asm::ParseError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		asm::parse_line(s)
+	}
 }
 
 #[cfg(test)]
@@ -267,4 +480,363 @@ mod test {
 		let i = Instruction::decode(0x1300004C).unwrap();
 		assert!(matches!(i, Instruction::Memory(_)));
 	}
+
+	#[test]
+	fn decode_errors() {
+		let rrr = rrr::Instruction {
+			op: BinOp::Add,
+			dest: Register::r1(),
+			lhs: Register::r2(),
+			rhs: Register::r3(),
+			shift: Shift::default(),
+		}.encode();
+
+		let value = rrr | (18 << 23);
+		assert_eq!(Instruction::decode(value), Err(DecodeError::new(DecodeErrorKind::UnknownBinOp(18), value, 27, 23)));
+
+		let value = rrr | (6 << 5);
+		assert_eq!(Instruction::decode(value), Err(DecodeError::new(DecodeErrorKind::UnknownShiftKind(6), value, 7, 5)));
+
+		assert_eq!(
+			rri::Instruction::decode(rrr),
+			Err(DecodeError::new(DecodeErrorKind::UnexpectedKind(Kind::Rrr), rrr, 31, 30))
+		);
+
+		let ri = memory::ri::Instruction {
+			op: LoadStoreOp { op: LoadStore::Load, width: Width::Word },
+			rd: Register::r1(),
+			rs: Register::r2(),
+			imm: -4,
+		}.encode();
+
+		let value = ri | (1 << 28);
+		assert_eq!(Instruction::decode(value), Err(DecodeError::new(DecodeErrorKind::ReservedBits, value, 29, 27)));
+
+		let value = ri | (3 << 23);
+		assert_eq!(Instruction::decode(value), Err(DecodeError::new(DecodeErrorKind::InvalidWidth(3), value, 24, 23)));
+	}
+
+	#[test]
+	fn eval() {
+		assert_eq!(BinOp::Add.eval(u32::MAX, 2, true), (1, Flags::from_result(1, false, false)));
+		assert_eq!(BinOp::Sub.eval(1, 2, true).0, u32::MAX);
+		assert_eq!(BinOp::Mul.eval(-3i32 as u32, 5, false).0, -15i32 as u32);
+
+		assert_eq!(BinOp::Div.eval(-7i32 as u32, 2, false).0, -3i32 as u32);
+		assert_eq!(BinOp::Div.eval(7, 0, false).0, u32::MAX);
+		assert_eq!(BinOp::Div.eval(i32::MIN as u32, -1i32 as u32, false).0, i32::MIN as u32);
+		assert_eq!(BinOp::Mod.eval(-7i32 as u32, 2, false).0, -1i32 as u32);
+		assert_eq!(BinOp::Mod.eval(7, 0, false).0, 7);
+		assert_eq!(BinOp::Mod.eval(i32::MIN as u32, -1i32 as u32, false).0, 0);
+
+		assert_eq!(BinOp::And.eval(0b1100, 0b1010, false).0, 0b1000);
+		assert_eq!(BinOp::Or.eval(0b1100, 0b1010, false).0, 0b1110);
+		assert_eq!(BinOp::Xor.eval(0b1100, 0b1010, false).0, 0b0110);
+
+		assert_eq!(BinOp::Shl.eval(1, 33, false).0, 2);
+		assert_eq!(BinOp::Asl.eval(1, 31, false).0, 0x80000000);
+		assert_eq!(BinOp::Shr.eval(0x80000000, 31, false).0, 1);
+		assert_eq!(BinOp::Asr.eval(0x80000000, 31, false).0, u32::MAX);
+		assert_eq!(BinOp::Rol.eval(0x80000001, 1, false).0, 3);
+		assert_eq!(BinOp::Ror.eval(3, 1, false).0, 0x80000001);
+
+		assert_eq!(BinOp::Not.eval(0, 1234, true).0, u32::MAX);
+		assert_eq!(BinOp::Neg.eval(1, 1234, true).0, u32::MAX);
+	}
+
+	#[test]
+	fn eval_flags() {
+		let flags = |overflow, carry, zero, negative| Flags { overflow, carry, zero, negative };
+
+		assert_eq!(BinOp::Addcc.eval(1, 2, true), (4, flags(false, false, false, false)));
+		assert_eq!(BinOp::Addcc.eval(u32::MAX, 1, false), (0, flags(false, true, true, false)));
+		assert_eq!(BinOp::Addcc.eval(u32::MAX, 0, true), (0, flags(false, true, true, false)));
+		assert_eq!(BinOp::Addcc.eval(i32::MAX as u32, 1, false), (0x80000000, flags(true, false, false, true)));
+
+		assert_eq!(BinOp::Subcc.eval(5, 5, false), (0, flags(false, false, true, false)));
+		assert_eq!(BinOp::Subcc.eval(5, 5, true), (u32::MAX, flags(false, true, false, true)));
+		assert_eq!(BinOp::Subcc.eval(0, 1, false), (u32::MAX, flags(false, true, false, true)));
+		assert_eq!(BinOp::Subcc.eval(i32::MIN as u32, 1, false), (i32::MAX as u32, flags(true, false, false, false)));
+	}
+
+	#[test]
+	fn condition_holds() {
+		let compare = |lhs: i32, rhs: i32| BinOp::Subcc.eval(lhs as u32, rhs as u32, false).1;
+
+		for (lhs, rhs) in [(5, 3), (3, 5), (4, 4), (-1, 1), (1, -1), (i32::MIN, 1), (i32::MAX, -1)] {
+			let flags = compare(lhs, rhs);
+			assert!(Condition::Always.holds(&flags));
+			assert_eq!(Condition::Zero.holds(&flags), lhs == rhs);
+			assert_eq!(Condition::NotZero.holds(&flags), lhs != rhs);
+			assert_eq!(Condition::GreaterThan.holds(&flags), lhs > rhs, "{lhs} > {rhs}");
+			assert_eq!(Condition::Carry.holds(&flags), (lhs as u32) < (rhs as u32));
+		}
+
+		let flags = Flags::default();
+		assert!(!Condition::Overflow.holds(&flags));
+		assert!(!Condition::Negative.holds(&flags));
+		assert!(Condition::NotNegative.holds(&flags));
+
+		let flags = BinOp::Addcc.eval(i32::MAX as u32, 1, false).1;
+		assert!(Condition::Overflow.holds(&flags));
+		assert!(Condition::Negative.holds(&flags));
+		assert!(!Condition::NotNegative.holds(&flags));
+	}
+
+	#[test]
+	fn abi_display() {
+		let abi = |text: &str| format!("{:#}", text.parse::<Instruction>().unwrap());
+
+		assert_eq!(abi("sub r3, r4, r5 asr 7"), "sub a2, a3, a4 asr 7");
+		assert_eq!(abi("add.nz r31, r30, 12"), "add.nz pc, lr, 12");
+		assert_eq!(abi("ld.w r1, [r28 + r20 << 2]"), "ld.w a0, [sp + t0 << 2]");
+		assert_eq!(abi("st.b r10, [r29 - 12]"), "st.b o0, [fp - 12]");
+		assert_eq!(abi("csr.ld.b r0, 0x141"), "csr.ld.b z, 0x141");
+		assert_eq!(abi("j -8"), "j .-8");
+
+		let registry = csr::registry::Registry::builtin();
+		let csr: Instruction = "csr.ld r16, 0x44".parse().unwrap();
+		assert_eq!(format!("{:#}", csr.display_with(&registry)), "csr.ld l0, isr.err1");
+	}
+
+	#[test]
+	fn control_flow() {
+		let i = |text: &str| text.parse::<Instruction>().unwrap();
+
+		for ret in ["ret", "add.z pc, lr, 0", "add pc, lr, r0", "add pc, r0, lr"] {
+			assert!(i(ret).is_return(), "{ret}");
+			assert!(i(ret).writes_pc(), "{ret}");
+			assert_eq!(i(ret).branch_target(0x100), None, "{ret}");
+		}
+		for not_ret in ["add pc, lr, 4", "add pc, r0, lr << 2", "sub pc, lr, 0", "add r1, lr, 0", "jr r5"] {
+			assert!(!i(not_ret).is_return(), "{not_ret}");
+		}
+
+		assert_eq!(i("jr r5").to_string(), "add r31, r5, 0");
+		assert!(i("add lr, pc, 4").is_link());
+		assert!(!i("add pc, pc, 4").is_link());
+		assert!(!i("add.z lr, pc, 4").is_link());
+
+		assert_eq!(i("j .-8").branch_target(0x100), Some(0xf8));
+		assert_eq!(i("add.z pc, pc, 4").branch_target(0x100), Some(0x108));
+		assert_eq!(i("add pc, r0, -4").branch_target(0x100), Some(0xffff_fffc));
+		assert_eq!(i("add pc, r5, 4").branch_target(0x100), None);
+		assert_eq!(i("sub pc, pc, 4").branch_target(0x100), None);
+
+		assert!(i("ld.w pc, [sp]").writes_pc());
+		assert!(i("csr.ld pc, 0xf8").writes_pc());
+		assert!(!i("st.w pc, [sp]").writes_pc());
+		assert!(!i("add r1, pc, 0").writes_pc());
+	}
+
+	#[test]
+	fn kind_round_trip() {
+		for kind in (0..8).filter_map(Kind::from_u32) {
+			assert_eq!(Kind::decode(kind.encode()), Ok(kind));
+		}
+	}
+
+	#[test]
+	fn reserved() {
+		let i = Instruction::decode(0x20000000).unwrap();
+		assert!(matches!(i, Instruction::Reserved0010(r) if r.value() == 0x20000000));
+		assert_eq!(i.encode(), 0x20000000);
+		assert_eq!(i.to_string(), ".word 0x20000000");
+
+		let i = Instruction::decode(0x3abcdef1).unwrap();
+		assert!(matches!(i, Instruction::Reserved0011(r) if r.value() == 0x3abcdef1));
+		assert_eq!(i.encode(), 0x3abcdef1);
+		assert_eq!(i.to_string(), ".word 0x3abcdef1");
+		assert_eq!(i.to_string().parse::<Instruction>(), Ok(i));
+
+		assert_eq!(
+			misc::Reserved0010::decode(0x30000000),
+			Err(DecodeError::new(DecodeErrorKind::UnexpectedKind(Kind::Reserved0011), 0x30000000, 31, 28))
+		);
+	}
+
+	#[test]
+	fn try_encode() {
+		let mut rri = rri::Instruction {
+			op: BinOp::Add,
+			cond: Condition::Always,
+			dest: Register::r1(),
+			src: Register::r2(),
+			imm: 2047,
+		};
+		assert_eq!(rri.try_encode(), Ok(rri.encode()));
+		rri.imm = 2048;
+		assert_eq!(rri.try_encode(), Err(EncodeError::ImmediateOutOfRange { value: 2048, bits: 12 }));
+
+		let rrr = rrr::Instruction {
+			op: BinOp::Add,
+			dest: Register::r1(),
+			lhs: Register::r2(),
+			rhs: Register::r3(),
+			shift: Shift { kind: ShiftKind::Shl, shift: 32 },
+		};
+		assert_eq!(Instruction::Rrr(rrr).try_encode(), Err(EncodeError::ShiftOutOfRange(32)));
+
+		let csr = csr::Instruction {
+			op: LoadStoreOp { op: LoadStore::Load, width: Width::Word },
+			reg: Register::r1(),
+			imm: 1 << 18,
+		};
+		assert_eq!(csr.try_encode(), Err(EncodeError::ImmediateOutOfRange { value: 1 << 18, bits: 18 }));
+
+		let jump = jump::Instruction { imm: 6 };
+		assert_eq!(jump.try_encode(), Err(EncodeError::MisalignedOffset(6)));
+	}
+
+	#[test]
+	fn memory_ri_imm_round_trip() {
+		for imm in [-4096, -1, 0, 1, 4095] {
+			let i = Instruction::Memory(memory::Instruction::Ri(memory::ri::Instruction {
+				op: LoadStoreOp { op: LoadStore::Store, width: Width::Short },
+				rd: Register::r1(),
+				rs: Register::r2(),
+				imm,
+			}));
+			assert_eq!(Instruction::decode(i.try_encode().unwrap()), Ok(i));
+		}
+	}
+
+	#[test]
+	fn display_binop() {
+		let ops = [
+			(BinOp::Add, "add"), (BinOp::Sub, "sub"), (BinOp::Mul, "mul"),
+			(BinOp::Div, "div"), (BinOp::Mod, "mod"), (BinOp::And, "and"),
+			(BinOp::Or, "or"), (BinOp::Xor, "xor"), (BinOp::Shl, "shl"),
+			(BinOp::Shr, "shr"), (BinOp::Asl, "asl"), (BinOp::Asr, "asr"),
+			(BinOp::Rol, "rol"), (BinOp::Ror, "ror"), (BinOp::Not, "not"),
+			(BinOp::Neg, "neg"), (BinOp::Addcc, "addcc"), (BinOp::Subcc, "subcc"),
+		];
+
+		for (op, text) in ops {
+			assert_eq!(op.to_string(), text);
+		}
+	}
+
+	#[test]
+	fn display_condition() {
+		let conds = [
+			(Condition::Always, "al"), (Condition::Overflow, "o"),
+			(Condition::Carry, "c"), (Condition::Zero, "z"),
+			(Condition::Negative, "n"), (Condition::NotZero, "nz"),
+			(Condition::NotNegative, "nn"), (Condition::GreaterThan, "gt"),
+		];
+
+		for (cond, text) in conds {
+			assert_eq!(cond.to_string(), text);
+		}
+	}
+
+	#[test]
+	fn display_shift() {
+		let shifts = [
+			(ShiftKind::Shl, "<< 2"), (ShiftKind::Shr, ">> 2"),
+			(ShiftKind::Asl, "asl 2"), (ShiftKind::Asr, "asr 2"),
+			(ShiftKind::Rol, "rol 2"), (ShiftKind::Ror, "ror 2"),
+		];
+
+		for (kind, text) in shifts {
+			assert_eq!(Shift { kind, shift: 2 }.to_string(), text);
+		}
+	}
+
+	#[test]
+	fn display_load_store_op() {
+		let ops = [
+			(LoadStore::Load, Width::Byte, "ld.b"), (LoadStore::Load, Width::Short, "ld.s"),
+			(LoadStore::Load, Width::Word, "ld.w"), (LoadStore::Store, Width::Byte, "st.b"),
+			(LoadStore::Store, Width::Short, "st.s"), (LoadStore::Store, Width::Word, "st.w"),
+		];
+
+		for (op, width, text) in ops {
+			assert_eq!(LoadStoreOp { op, width }.to_string(), text);
+		}
+	}
+
+	#[test]
+	fn display_rrr() {
+		let mut i = rrr::Instruction {
+			op: BinOp::Sub,
+			dest: Register::r3(),
+			lhs: Register::r4(),
+			rhs: Register::r5(),
+			shift: Shift::default(),
+		};
+		assert_eq!(Instruction::Rrr(i).to_string(), "sub r3, r4, r5");
+
+		i.shift = Shift { kind: ShiftKind::Asr, shift: 7 };
+		assert_eq!(Instruction::Rrr(i).to_string(), "sub r3, r4, r5 asr 7");
+
+		i.shift = Shift { kind: ShiftKind::Ror, shift: 0 };
+		assert_eq!(Instruction::Rrr(i).to_string(), "sub r3, r4, r5 ror 0");
+	}
+
+	#[test]
+	fn display_rri() {
+		let mut i = rri::Instruction {
+			op: BinOp::Add,
+			cond: Condition::NotZero,
+			dest: Register::r3(),
+			src: Register::r4(),
+			imm: 12,
+		};
+		assert_eq!(Instruction::Rri(i).to_string(), "add.nz r3, r4, 12");
+
+		i.cond = Condition::Always;
+		i.imm = -2048;
+		assert_eq!(Instruction::Rri(i).to_string(), "add r3, r4, -2048");
+	}
+
+	#[test]
+	fn display_memory() {
+		let rr = memory::rr::Instruction {
+			op: LoadStoreOp { op: LoadStore::Load, width: Width::Word },
+			rd: Register::r1(),
+			rs: Register::r2(),
+			rq: Register::r3(),
+			shift: Shift { kind: ShiftKind::Shl, shift: 2 },
+		};
+		assert_eq!(Instruction::Memory(memory::Instruction::Rr(rr)).to_string(), "ld.w r1, [r2 + r3 << 2]");
+
+		let rr = memory::rr::Instruction { shift: Shift::default(), ..rr };
+		assert_eq!(Instruction::Memory(memory::Instruction::Rr(rr)).to_string(), "ld.w r1, [r2 + r3]");
+
+		let mut ri = memory::ri::Instruction {
+			op: LoadStoreOp { op: LoadStore::Store, width: Width::Byte },
+			rd: Register::r1(),
+			rs: Register::r2(),
+			imm: 12,
+		};
+		assert_eq!(Instruction::Memory(memory::Instruction::Ri(ri)).to_string(), "st.b r1, [r2 + 12]");
+
+		ri.imm = -12;
+		assert_eq!(Instruction::Memory(memory::Instruction::Ri(ri)).to_string(), "st.b r1, [r2 - 12]");
+
+		ri.imm = 0;
+		assert_eq!(Instruction::Memory(memory::Instruction::Ri(ri)).to_string(), "st.b r1, [r2]");
+	}
+
+	#[test]
+	fn display_csr() {
+		let mut i = csr::Instruction {
+			op: LoadStoreOp { op: LoadStore::Store, width: Width::Word },
+			reg: Register::r5(),
+			imm: 0x140,
+		};
+		assert_eq!(Instruction::Csr(i).to_string(), "csr.st r5, 0x140");
+
+		i.op = LoadStoreOp { op: LoadStore::Load, width: Width::Byte };
+		i.imm = 0x141;
+		assert_eq!(Instruction::Csr(i).to_string(), "csr.ld.b r5, 0x141");
+	}
+
+	#[test]
+	fn display_jump() {
+		assert_eq!(Instruction::Jump(jump::Instruction { imm: 16 }).to_string(), "j .+16");
+		assert_eq!(Instruction::Jump(jump::Instruction { imm: -8 }).to_string(), "j .-8");
+	}
 }
\ No newline at end of file
@@ -1,6 +1,11 @@
 /* Copyright 2023 Robert Zieba, see LICENSE file for full license. */
+use std::fmt;
+
 use crate::{
+	DecodeError,
+	DecodeErrorKind,
 	Encode,
+	EncodeError,
 	Kind,
 };
 
@@ -14,13 +19,13 @@ pub enum Instruction {
 }
 
 impl Encode for Instruction {
-	fn decode(value: u32) -> Option<Instruction> {
+	fn decode(value: u32) -> Result<Instruction, DecodeError> {
 		let kind = Kind::decode(value)?;
 
 		match kind {
-			Kind::MemoryRr => Some(Instruction::Rr(rr::Instruction::decode(value)?)),
-			Kind::MemoryRi => Some(Instruction::Ri(ri::Instruction::decode(value)?)),
-			_ => None,
+			Kind::MemoryRr => Ok(Instruction::Rr(rr::Instruction::decode(value)?)),
+			Kind::MemoryRi => Ok(Instruction::Ri(ri::Instruction::decode(value)?)),
+			_ => Err(DecodeError::new(DecodeErrorKind::UnexpectedKind(kind), value, 31, 27)),
 		}
 	}
 
@@ -30,4 +35,20 @@ impl Encode for Instruction {
 			Instruction::Ri(i) => i.encode(),
 		}
 	}
-}
\ No newline at end of file
+
+	fn try_encode(&self) -> Result<u32, EncodeError> {
+		match self {
+			Instruction::Rr(i) => i.try_encode(),
+			Instruction::Ri(i) => i.try_encode(),
+		}
+	}
+}
+
+impl fmt::Display for Instruction {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Instruction::Rr(i) => i.fmt(f),
+			Instruction::Ri(i) => i.fmt(f),
+		}
+	}
+}
@@ -1,9 +1,15 @@
 /* Copyright 2023 Robert Zieba, see LICENSE file for full license. */
+use std::fmt;
+
 use crate::{
+	DecodeError,
+	DecodeErrorKind,
 	Encode,
+	EncodeError,
 	Kind,
 	
 	Register,
+	error::check_signed,
 	util::{
 		sign_contract,
 		sign_extend,
@@ -11,6 +17,9 @@ use crate::{
 	LoadStoreOp,
 };
 
+/// Width of the signed immediate field
+pub const IMM_BITS: u8 = 13;
+
 use bitfield::bitfield;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -25,6 +34,7 @@ bitfield! {
 	struct Bitfield(u32);
 	impl Debug;
 	pub kind, set_kind : 31, 28;
+	pub reserved, set_reserved : 29, 27;
 	pub op, set_op : 26, 23;
 	pub rd, set_rd : 22, 18;
 	pub rs, set_rs : 17, 13;
@@ -32,20 +42,24 @@ bitfield! {
 }
 
 impl Encode for Instruction {
-	fn decode(value: u32) -> Option<Self> {
+	fn decode(value: u32) -> Result<Self, DecodeError> {
 		let bitfield = Bitfield(value);
-		let op = LoadStoreOp::decode(bitfield.op())?;
 		let kind = Kind::decode(value)?;
 
 		if kind != Kind::MemoryRi {
-				return None;
+			return Err(DecodeError::new(DecodeErrorKind::UnexpectedKind(kind), value, 31, 30));
+		}
+
+		if bitfield.reserved() != 0 {
+			return Err(DecodeError::new(DecodeErrorKind::ReservedBits, value, 29, 27));
 		}
 
-		Some(Instruction { 
-			op: op,
+		let op = LoadStoreOp::decode(bitfield.op()).map_err(|e| e.within(value, 23))?;
+		Ok(Instruction { 
+			op,
 			rd: Register::new(bitfield.rd() as u8).unwrap(),
 			rs: Register::new(bitfield.rs() as u8).unwrap(), 
-			imm: sign_extend(bitfield.imm(), 12) as i16,
+			imm: sign_extend(bitfield.imm(), IMM_BITS as i8) as i16,
 		})
 	}
 
@@ -55,9 +69,27 @@ impl Encode for Instruction {
 		bitfield.set_op(self.op.encode());
 		bitfield.set_rd(self.rd.as_u8() as u32);
 		bitfield.set_rs(self.rs.as_u8() as u32);
-		bitfield.set_imm(sign_contract(self.imm as i32, 14));
+		bitfield.set_imm(sign_contract(self.imm as i32, IMM_BITS));
 
 		bitfield.0
 
 	}
-}
\ No newline at end of file
+
+	fn try_encode(&self) -> Result<u32, EncodeError> {
+		check_signed(self.imm as i64, IMM_BITS)?;
+		Ok(self.encode())
+	}
+}
+
+impl fmt::Display for Instruction {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let abi = f.alternate();
+		write!(f, "{} {}, [{}", self.op, self.rd.named(abi), self.rs.named(abi))?;
+		match self.imm {
+			0 => {},
+			imm if imm < 0 => write!(f, " - {}", -(imm as i32))?,
+			imm => write!(f, " + {}", imm)?,
+		}
+		f.write_str("]")
+	}
+}
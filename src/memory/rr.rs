@@ -1,15 +1,19 @@
 /* Copyright 2023 Robert Zieba, see LICENSE file for full license. */
+use std::fmt;
+
 use crate::{
+	DecodeError,
+	DecodeErrorKind,
 	Encode,
+	EncodeError,
 	Kind,
 	LoadStoreOp,
 	Shift,
 	ShiftKind,
 	Register,
+	error::check_shift,
 };
 
-use num_traits::FromPrimitive;
-
 use bitfield::bitfield;
 
 bitfield! {
@@ -34,22 +38,22 @@ pub struct Instruction {
 }
 
 impl Encode for Instruction {
-	fn decode(value: u32) -> Option<Self> {
+	fn decode(value: u32) -> Result<Self, DecodeError> {
 		let bitfield = Bitfield(value);
-		let op = LoadStoreOp::decode(bitfield.op())?;
 		let kind = Kind::decode(value)?;
 
 		if kind != Kind::MemoryRr {
-				return None;
+			return Err(DecodeError::new(DecodeErrorKind::UnexpectedKind(kind), value, 31, 27));
 		}
 
-		Some(Instruction {
-			op: op,
+		let op = LoadStoreOp::decode(bitfield.op()).map_err(|e| e.within(value, 23))?;
+		Ok(Instruction {
+			op,
 			rd: Register::new(bitfield.rd() as u8).unwrap(),
 			rs: Register::new(bitfield.rs() as u8).unwrap(),
 			rq: Register::new(bitfield.rq() as u8).unwrap(),
 			shift: Shift {
-				kind: ShiftKind::from_u8(bitfield.shift_kind() as u8)?,
+				kind: ShiftKind::decode(bitfield.shift_kind()).map_err(|e| e.within(value, 5))?,
 				shift: bitfield.shift() as u8,
 			}
 		})
@@ -68,4 +72,20 @@ impl Encode for Instruction {
 		bitfield.0
 
 	}
-}
\ No newline at end of file
+
+	fn try_encode(&self) -> Result<u32, EncodeError> {
+		check_shift(self.shift.shift)?;
+		Ok(self.encode())
+	}
+}
+
+impl fmt::Display for Instruction {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let abi = f.alternate();
+		write!(f, "{} {}, [{} + {}", self.op, self.rd.named(abi), self.rs.named(abi), self.rq.named(abi))?;
+		if !self.shift.is_none() {
+			write!(f, " {}", self.shift)?;
+		}
+		f.write_str("]")
+	}
+}
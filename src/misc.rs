@@ -1,32 +1,71 @@
 /* Copyright 2023 Robert Zieba, see LICENSE file for full license. */
-use crate::Encode;
+use std::fmt;
 
+use crate::{
+    DecodeError,
+    DecodeErrorKind,
+    Encode,
+    Kind,
+};
+
+/// Word in an encoding space reserved for future instructions, kept as-is so it can be re-encoded losslessly
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Reserved0010 {
     value: u32,
 }
 
+impl Reserved0010 {
+    pub fn value(&self) -> u32 {
+        self.value
+    }
+}
+
 impl Encode for Reserved0010 {
-    fn decode(_value: u32) -> Option<Self> {
-        todo!()
+    fn decode(value: u32) -> Result<Self, DecodeError> {
+        match Kind::decode(value)? {
+            Kind::Reserved0010 => Ok(Reserved0010 { value }),
+            kind => Err(DecodeError::new(DecodeErrorKind::UnexpectedKind(kind), value, 31, 28)),
+        }
     }
 
     fn encode(&self) -> u32 {
-        todo!()
+        self.value
+    }
+}
+
+impl fmt::Display for Reserved0010 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, ".word {:#010x}", self.value)
     }
 }
 
+/// Same as [`Reserved0010`] for the `0011` encoding space
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Reserved0011 {
     value: u32,
 }
 
+impl Reserved0011 {
+    pub fn value(&self) -> u32 {
+        self.value
+    }
+}
+
 impl Encode for Reserved0011 {
-    fn decode(_value: u32) -> Option<Self> {
-        todo!()
+    fn decode(value: u32) -> Result<Self, DecodeError> {
+        match Kind::decode(value)? {
+            Kind::Reserved0011 => Ok(Reserved0011 { value }),
+            kind => Err(DecodeError::new(DecodeErrorKind::UnexpectedKind(kind), value, 31, 28)),
+        }
     }
 
     fn encode(&self) -> u32 {
-        todo!()
+        self.value
+    }
+}
+
+impl fmt::Display for Reserved0011 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, ".word {:#010x}", self.value)
     }
 }
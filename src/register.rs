@@ -1,5 +1,11 @@
 /* Copyright 2023 Robert Zieba, see LICENSE file for full license. */
-use crate::Encode;
+use std::fmt;
+
+use crate::{
+	DecodeError,
+	DecodeErrorKind,
+	Encode,
+};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Register(u8);
@@ -320,11 +326,62 @@ impl Register {
 	pub fn t7() -> Register {
 		Self::temp(7).unwrap()
 	}
+
+	/// Name under the calling convention, the inverse of `from_name` for ABI names
+	pub fn abi_name(self) -> String {
+		match self.0 {
+			0 => "z".into(),
+			1..=9 => format!("a{}", self.0 - 1),
+			10..=15 => format!("o{}", self.0 - 10),
+			16..=19 => format!("l{}", self.0 - 16),
+			20..=27 => format!("t{}", self.0 - 20),
+			28 => "sp".into(),
+			29 => "fp".into(),
+			30 => "lr".into(),
+			_ => "pc".into(),
+		}
+	}
+
+	/// Displays the ABI name if `abi` is set and the numeric name otherwise
+	pub(crate) fn named(self, abi: bool) -> Named {
+		Named {
+			reg: self,
+			abi,
+		}
+	}
+
+	/// Looks up a register by its numeric (`r12`) or ABI (`sp`, `a0`, `t3`...) name
+	pub fn from_name(name: &str) -> Option<Register> {
+		match name {
+			"pc" => return Some(Self::pc()),
+			"lr" => return Some(Self::lr()),
+			"fp" => return Some(Self::fp()),
+			"sp" => return Some(Self::sp()),
+			"z" => return Some(Self::z()),
+			_ => {},
+		}
+
+		let split = name.find(|c: char| c.is_ascii_digit())?;
+		let (prefix, index) = name.split_at(split);
+		let index = index.parse::<u8>().ok()?;
+		match prefix {
+			"r" => Self::new(index),
+			"a" => Self::arg(index),
+			"o" => Self::out(index),
+			"l" => Self::local(index),
+			"t" => Self::temp(index),
+			_ => None,
+		}
+	}
 }
 
 impl Encode for Register {
-	fn decode(value: u32) -> Option<Register> {
-		Register::new(value as u8)
+	fn decode(value: u32) -> Result<Register, DecodeError> {
+		if value > 31 {
+			return Err(DecodeError::new(DecodeErrorKind::InvalidRegister(value), value, 4, 0));
+		}
+
+		Ok(Register(value as u8))
 	}
 
 	fn encode(&self) -> u32 {
@@ -332,6 +389,35 @@ impl Encode for Register {
 	}
 }
 
+/// Numeric name, or the ABI name with the alternate flag (`{:#}`)
+///
+/// Instructions forward the flag to their registers.
+impl fmt::Display for Register {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if f.alternate() {
+			f.write_str(&self.abi_name())
+		} else {
+			write!(f, "r{}", self.0)
+		}
+	}
+}
+
+/// See `Register::named`
+pub(crate) struct Named {
+	reg: Register,
+	abi: bool,
+}
+
+impl fmt::Display for Named {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.abi {
+			f.write_str(&self.reg.abi_name())
+		} else {
+			write!(f, "r{}", self.reg.0)
+		}
+	}
+}
+
 #[cfg(test)]
 mod test {
 	use super::*;
@@ -372,6 +458,42 @@ mod test {
 		assert_eq!(Register::out(5).unwrap().0, 15);
 	}
 
+	// Names
+	#[test]
+	fn abi_name() {
+		for reg in (0..32).filter_map(Register::new) {
+			assert_eq!(Register::from_name(&reg.abi_name()), Some(reg));
+		}
+		assert_eq!(Register::r9().abi_name(), "a8");
+		assert_eq!(Register::r19().abi_name(), "l3");
+		assert_eq!(format!("{:#}", Register::sp()), "sp");
+		assert_eq!(format!("{}", Register::sp()), "r28");
+	}
+
+	#[test]
+	fn from_name() {
+		assert_eq!(Register::from_name("r0"), Some(Register::r0()));
+		assert_eq!(Register::from_name("r31"), Some(Register::r31()));
+		assert_eq!(Register::from_name("pc"), Some(Register::pc()));
+		assert_eq!(Register::from_name("lr"), Some(Register::lr()));
+		assert_eq!(Register::from_name("fp"), Some(Register::fp()));
+		assert_eq!(Register::from_name("sp"), Some(Register::sp()));
+		assert_eq!(Register::from_name("z"), Some(Register::z()));
+		assert_eq!(Register::from_name("a0"), Some(Register::a0()));
+		assert_eq!(Register::from_name("o5"), Some(Register::o5()));
+		assert_eq!(Register::from_name("l3"), Some(Register::l3()));
+		assert_eq!(Register::from_name("t3"), Some(Register::t3()));
+	}
+
+	#[test]
+	fn from_name_err() {
+		assert!(Register::from_name("r32").is_none());
+		assert!(Register::from_name("a9").is_none());
+		assert!(Register::from_name("l4").is_none());
+		assert!(Register::from_name("x1").is_none());
+		assert!(Register::from_name("r").is_none());
+	}
+
 	// Local
 	#[test]
 	fn local_err() {
@@ -1,20 +1,30 @@
 /* Copyright 2023 Robert Zieba, see LICENSE file for full license. */
+use std::fmt;
+
 use bitfield::bitfield;
 use log::debug;
 use num_derive::{ FromPrimitive, ToPrimitive };
-use num_traits::{ FromPrimitive, ToPrimitive };
+use num_traits::FromPrimitive;
 
 use crate::{
 	Encode,
 	BinOp,
+	Flags,
+	DecodeError,
+	DecodeErrorKind,
+	EncodeError,
 	Kind,
 	Register,
+	error::check_signed,
 	util::{
 		sign_contract,
 		sign_extend,
 	},
 };
 
+/// Width of the signed immediate field
+pub const IMM_BITS: u8 = 12;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive, ToPrimitive)]
 pub enum Condition {
 	Always,
@@ -28,12 +38,47 @@ pub enum Condition {
 }
 
 impl Encode for Condition {
-	fn decode(value: u32) -> Option<Self> {
+	fn decode(value: u32) -> Result<Self, DecodeError> {
 		Condition::from_u32(value)
+			.ok_or(DecodeError::new(DecodeErrorKind::UnknownCondition(value), value, 2, 0))
 	}
 
 	fn encode(&self) -> u32 {
-		self.to_u32().unwrap()
+		*self as u32
+	}
+}
+
+impl Condition {
+	/// True if an instruction with this condition executes given `flags`
+	///
+	/// `GreaterThan` is a signed comparison, after `subcc` with no borrow in it holds when `lhs > rhs`.
+	pub fn holds(&self, flags: &Flags) -> bool {
+		match self {
+			Condition::Always => true,
+			Condition::Overflow => flags.overflow,
+			Condition::Carry => flags.carry,
+			Condition::Zero => flags.zero,
+			Condition::Negative => flags.negative,
+			Condition::NotZero => !flags.zero,
+			Condition::NotNegative => !flags.negative,
+			Condition::GreaterThan => !flags.zero && flags.negative == flags.overflow,
+		}
+	}
+}
+
+impl fmt::Display for Condition {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let suffix = match self {
+			Condition::Always => "al",
+			Condition::Overflow => "o",
+			Condition::Carry => "c",
+			Condition::Zero => "z",
+			Condition::Negative => "n",
+			Condition::NotZero => "nz",
+			Condition::NotNegative => "nn",
+			Condition::GreaterThan => "gt",
+		};
+		f.write_str(suffix)
 	}
 }
 
@@ -58,21 +103,21 @@ bitfield! {
 }
 
 impl Encode for Instruction {
-	fn decode(value: u32) -> Option<Instruction> {
+	fn decode(value: u32) -> Result<Instruction, DecodeError> {
 		let bitfield = Bitfield(value);
 		let kind = Kind::decode(value)?;
 	
 		if kind != Kind::Rri {
 			debug!("Not an RRI instruction, got {:?}", kind);
-			return None;
+			return Err(DecodeError::new(DecodeErrorKind::UnexpectedKind(kind), value, 31, 30));
 		}
 
-		Some(Instruction {
-			op: BinOp::from_u32(bitfield.op())?,
-			cond: Condition::from_u32(bitfield.cond())?,
+		Ok(Instruction {
+			op: BinOp::decode(bitfield.op()).map_err(|e| e.within(value, 25))?,
+			cond: Condition::decode(bitfield.cond()).map_err(|e| e.within(value, 12))?,
 			dest: Register::new(bitfield.rd() as u8).unwrap(),
 			src: Register::new(bitfield.rs() as u8).unwrap(),
-			imm: sign_extend(bitfield.imm(), 12) as i16,
+			imm: sign_extend(bitfield.imm(), IMM_BITS as i8) as i16,
 		})
 	}
 
@@ -82,7 +127,23 @@ impl Encode for Instruction {
 		bitfield.set_rd(self.dest.encode());
 		bitfield.set_rs(self.src.encode());
 		bitfield.set_cond(self.cond.encode());
-		bitfield.set_imm(sign_contract(self.imm as i32, 12));
+		bitfield.set_imm(sign_contract(self.imm as i32, IMM_BITS));
 		bitfield.0
 	}
-}
\ No newline at end of file
+
+	fn try_encode(&self) -> Result<u32, EncodeError> {
+		check_signed(self.imm as i64, IMM_BITS)?;
+		Ok(self.encode())
+	}
+}
+
+impl fmt::Display for Instruction {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let abi = f.alternate();
+		write!(f, "{}", self.op)?;
+		if self.cond != Condition::Always {
+			write!(f, ".{}", self.cond)?;
+		}
+		write!(f, " {}, {}, {}", self.dest.named(abi), self.src.named(abi), self.imm)
+	}
+}
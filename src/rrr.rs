@@ -1,15 +1,20 @@
 /* Copyright 2023 Robert Zieba, see LICENSE file for full license. */
+use std::fmt;
+
 use bitfield::bitfield;
-use num_traits::FromPrimitive;
 use log::debug;
 
 use crate::{
 	BinOp,
+	DecodeError,
+	DecodeErrorKind,
 	Encode,
+	EncodeError,
 	Kind,
 	Register,
 	Shift,
-	ShiftKind
+	ShiftKind,
+	error::check_shift,
 };
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -34,22 +39,22 @@ bitfield! {
 }
 
 impl Encode for Instruction {
-	fn decode(value: u32) -> Option<Self> {
+	fn decode(value: u32) -> Result<Self, DecodeError> {
 		let bitfield = BitField(value);
 		let kind = Kind::decode(value)?;
 
 		if kind != Kind::Rrr {
 			debug!("Not an RRR instruction, got {:?}", kind);
-			return None;
+			return Err(DecodeError::new(DecodeErrorKind::UnexpectedKind(kind), value, 31, 28));
 		}
 
-		Some(Instruction {
-			op: BinOp::from_u32(bitfield.op())?,
+		Ok(Instruction {
+			op: BinOp::decode(bitfield.op()).map_err(|e| e.within(value, 23))?,
 			dest: Register::new(bitfield.rd() as u8).unwrap(),
 			lhs: Register::new(bitfield.rs() as u8).unwrap(),
 			rhs: Register::new(bitfield.rq() as u8).unwrap(),
 			shift: Shift {
-				kind: ShiftKind::from_u8(bitfield.shift_kind() as u8)?,
+				kind: ShiftKind::decode(bitfield.shift_kind()).map_err(|e| e.within(value, 5))?,
 				shift: bitfield.shift() as u8,
 			}
 		})
@@ -65,4 +70,20 @@ impl Encode for Instruction {
 		bitfield.set_shift(self.shift.shift as u32);
 		bitfield.0
 	}
-}
\ No newline at end of file
+
+	fn try_encode(&self) -> Result<u32, EncodeError> {
+		check_shift(self.shift.shift)?;
+		Ok(self.encode())
+	}
+}
+
+impl fmt::Display for Instruction {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let abi = f.alternate();
+		write!(f, "{} {}, {}, {}", self.op, self.dest.named(abi), self.lhs.named(abi), self.rhs.named(abi))?;
+		if !self.shift.is_none() {
+			write!(f, " {}", self.shift)?;
+		}
+		Ok(())
+	}
+}
@@ -1,7 +1,13 @@
+use std::fmt;
+
 use num_derive::{ FromPrimitive, ToPrimitive };
-use num_traits::{ FromPrimitive, ToPrimitive };
+use num_traits::FromPrimitive;
 
-use crate::Encode;
+use crate::{
+	DecodeError,
+	DecodeErrorKind,
+	Encode,
+};
 
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, FromPrimitive, ToPrimitive)]
 pub enum Kind {
@@ -15,12 +21,27 @@ pub enum Kind {
 }
 
 impl Encode for Kind {
-	fn decode(value: u32) -> Option<Kind> {
+	fn decode(value: u32) -> Result<Kind, DecodeError> {
 		Kind::from_u32(value)
+			.ok_or(DecodeError::new(DecodeErrorKind::UnknownShiftKind(value), value, 2, 0))
 	}
 
 	fn encode(&self) -> u32 {
-		self.to_u32().unwrap()
+		*self as u32
+	}
+}
+
+impl fmt::Display for Kind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let op = match self {
+			Kind::Shl => "<<",
+			Kind::Shr => ">>",
+			Kind::Asl => "asl",
+			Kind::Asr => "asr",
+			Kind::Rol => "rol",
+			Kind::Ror => "ror",
+		};
+		f.write_str(op)
 	}
 }
 
@@ -28,4 +49,54 @@ impl Encode for Kind {
 pub struct Shift {
 	pub kind: Kind,
 	pub shift: u8,
-}
\ No newline at end of file
+}
+
+impl Shift {
+	/// True if applying this shift leaves the operand unchanged and it can be omitted from assembly
+	pub fn is_none(&self) -> bool {
+		self.kind == Kind::Shl && self.shift == 0
+	}
+
+	/// Shifts `value`, only the low 5 bits of the shift amount are used like in the encoded field
+	pub fn apply(&self, value: u32) -> u32 {
+		let amount = (self.shift & 0x1f) as u32;
+		match self.kind {
+			Kind::Shl | Kind::Asl => value << amount,
+			Kind::Shr => value >> amount,
+			Kind::Asr => ((value as i32) >> amount) as u32,
+			Kind::Rol => value.rotate_left(amount),
+			Kind::Ror => value.rotate_right(amount),
+		}
+	}
+}
+
+impl fmt::Display for Shift {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{} {}", self.kind, self.shift)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn shift(kind: Kind, shift: u8) -> Shift {
+		Shift {
+			kind,
+			shift,
+		}
+	}
+
+	#[test]
+	fn apply() {
+		assert_eq!(Shift::default().apply(0x1234), 0x1234);
+		assert_eq!(shift(Kind::Shl, 4).apply(0x80000001), 0x10);
+		assert_eq!(shift(Kind::Asl, 4).apply(0x80000001), 0x10);
+		assert_eq!(shift(Kind::Shr, 4).apply(0x80000010), 0x08000001);
+		assert_eq!(shift(Kind::Asr, 4).apply(0x80000010), 0xf8000001);
+		assert_eq!(shift(Kind::Rol, 4).apply(0x80000001), 0x18);
+		assert_eq!(shift(Kind::Ror, 4).apply(0x80000001), 0x18000000);
+		assert_eq!(shift(Kind::Shl, 31).apply(1), 0x80000000);
+		assert_eq!(shift(Kind::Shl, 33).apply(1), 2);
+	}
+}
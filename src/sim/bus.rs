@@ -0,0 +1,104 @@
+/* Copyright 2023 Robert Zieba, see LICENSE file for full license. */
+use std::fmt;
+
+use crate::Width;
+
+/// Access to an address nothing responds to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BusError {
+	pub addr: u32,
+}
+
+impl fmt::Display for BusError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "bus error at {:#010x}", self.addr)
+	}
+}
+
+impl std::error::Error for BusError {}
+
+/// Memory as seen by the CPU, values are zero extended to 32 bits
+pub trait Bus {
+	fn read(&mut self, addr: u32, width: Width) -> Result<u32, BusError>;
+	fn write(&mut self, addr: u32, width: Width, value: u32) -> Result<(), BusError>;
+}
+
+/// Little endian RAM covering `base..base + size`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ram {
+	base: u32,
+	bytes: Vec<u8>,
+}
+
+impl Ram {
+	pub fn new(base: u32, size: usize) -> Ram {
+		Ram {
+			base,
+			bytes: vec![0; size],
+		}
+	}
+
+	pub fn bytes(&self) -> &[u8] {
+		&self.bytes
+	}
+
+	fn range(&self, addr: u32, len: usize) -> Result<std::ops::Range<usize>, BusError> {
+		let start = addr.wrapping_sub(self.base) as usize;
+		let end = start.checked_add(len).ok_or(BusError { addr })?;
+		if addr < self.base || end > self.bytes.len() {
+			Err(BusError { addr })
+		} else {
+			Ok(start..end)
+		}
+	}
+
+	/// Copies `data` into memory starting at `addr`
+	pub fn load(&mut self, addr: u32, data: &[u8]) -> Result<(), BusError> {
+		let range = self.range(addr, data.len())?;
+		self.bytes[range].copy_from_slice(data);
+		Ok(())
+	}
+
+	/// Stores `words` in little endian order starting at `addr`
+	pub fn load_words(&mut self, addr: u32, words: &[u32]) -> Result<(), BusError> {
+		let data: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+		self.load(addr, &data)
+	}
+}
+
+impl Bus for Ram {
+	fn read(&mut self, addr: u32, width: Width) -> Result<u32, BusError> {
+		let range = self.range(addr, width.to_len() as usize)?;
+		let mut value = [0u8; 4];
+		value[..range.len()].copy_from_slice(&self.bytes[range]);
+		Ok(u32::from_le_bytes(value))
+	}
+
+	fn write(&mut self, addr: u32, width: Width, value: u32) -> Result<(), BusError> {
+		let range = self.range(addr, width.to_len() as usize)?;
+		let len = range.len();
+		self.bytes[range].copy_from_slice(&value.to_le_bytes()[..len]);
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn ram() {
+		let mut ram = Ram::new(0x100, 8);
+		ram.write(0x100, Width::Word, 0xdeadbeef).unwrap();
+		ram.write(0x104, Width::Short, 0x12345678).unwrap();
+		ram.write(0x107, Width::Byte, 0xab).unwrap();
+
+		assert_eq!(ram.bytes(), &[0xef, 0xbe, 0xad, 0xde, 0x78, 0x56, 0x00, 0xab]);
+		assert_eq!(ram.read(0x101, Width::Short), Ok(0xadbe));
+		assert_eq!(ram.read(0x104, Width::Word), Ok(0xab005678));
+
+		assert_eq!(ram.read(0xff, Width::Byte), Err(BusError { addr: 0xff }));
+		assert_eq!(ram.read(0x105, Width::Word), Err(BusError { addr: 0x105 }));
+		assert_eq!(ram.write(0x108, Width::Byte, 0), Err(BusError { addr: 0x108 }));
+	}
+}
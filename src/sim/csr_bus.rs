@@ -0,0 +1,248 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+use std::any::Any;
+use std::fmt;
+
+use crate::{
+	LoadStore,
+	Width,
+	csr::{
+		self,
+		registry::Block,
+	},
+};
+
+use super::BusError;
+
+/// Peripheral occupying one or more CSR blocks, offsets are relative to the start of its mapping
+pub trait CsrDevice: Any {
+	fn read(&mut self, offset: u32, width: Width) -> Result<u32, BusError>;
+	fn write(&mut self, offset: u32, width: Width, value: u32) -> Result<(), BusError>;
+}
+
+/// Reason a device couldn't be attached to a `CsrBus`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AttachError {
+	/// The block overlaps the one `existing` is attached to
+	Overlap {
+		block: String,
+		existing: String,
+	},
+	/// The block extends past the top of the CSR space, which `csr::IMM_BITS` of address can reach
+	PastEnd(String),
+}
+
+impl fmt::Display for AttachError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			AttachError::Overlap { block, existing } => write!(f, "block {block} overlaps attached block {existing}"),
+			AttachError::PastEnd(block) => write!(f, "block {block} extends past the end of the CSR space"),
+		}
+	}
+}
+
+impl std::error::Error for AttachError {}
+
+struct Mapping {
+	block: String,
+	base: u32,
+	end: u32,
+	device: Box<dyn CsrDevice>,
+}
+
+impl Mapping {
+	fn offset(&self, addr: u32) -> Option<u32> {
+		(self.base..self.end).contains(&addr).then(|| addr - self.base)
+	}
+}
+
+/// CSR address space made of devices attached to registry blocks
+///
+/// Errors reported by a device are at the CSR address rather than the device offset.
+#[derive(Default)]
+pub struct CsrBus {
+	mappings: Vec<Mapping>,
+}
+
+impl fmt::Debug for CsrBus {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_list()
+			.entries(self.mappings.iter().map(|m| (&m.block, m.base, m.end)))
+			.finish()
+	}
+}
+
+impl CsrBus {
+	pub fn new() -> CsrBus {
+		CsrBus {
+			mappings: Vec::new(),
+		}
+	}
+
+	/// Maps `device` over the `count` CSR blocks starting at `block.base`
+	pub fn attach(&mut self, block: &Block, device: impl CsrDevice) -> Result<(), AttachError> {
+		// Bounded by the CSR address space, not just u32
+		let end = block.end().ok_or_else(|| AttachError::PastEnd(block.name.clone()))?;
+		if let Some(existing) = self.mappings.iter().find(|m| block.base < m.end && m.base < end) {
+			return Err(AttachError::Overlap {
+				block: block.name.clone(),
+				existing: existing.block.clone(),
+			});
+		}
+
+		self.mappings.push(Mapping {
+			block: block.name.clone(),
+			base: block.base,
+			end,
+			device: Box::new(device),
+		});
+		Ok(())
+	}
+
+	/// Removes the device attached to `block`
+	pub fn detach(&mut self, block: &str) -> Option<Box<dyn CsrDevice>> {
+		let index = self.mappings.iter().position(|m| m.block == block)?;
+		Some(self.mappings.remove(index).device)
+	}
+
+	/// Device attached to `block`, if it's a `T`
+	pub fn device<T: CsrDevice>(&self, block: &str) -> Option<&T> {
+		let mapping = self.mappings.iter().find(|m| m.block == block)?;
+		(mapping.device.as_ref() as &dyn Any).downcast_ref()
+	}
+
+	/// Device attached to `block`, if it's a `T`
+	pub fn device_mut<T: CsrDevice>(&mut self, block: &str) -> Option<&mut T> {
+		let mapping = self.mappings.iter_mut().find(|m| m.block == block)?;
+		(mapping.device.as_mut() as &mut dyn Any).downcast_mut()
+	}
+
+	/// Whether a device responds to `addr`
+	pub fn is_mapped(&self, addr: u32) -> bool {
+		self.mappings.iter().any(|m| m.offset(addr).is_some())
+	}
+
+	fn device_at(&mut self, addr: u32) -> Result<(&mut dyn CsrDevice, u32), BusError> {
+		self.mappings.iter_mut()
+			.find_map(|m| m.offset(addr).map(|offset| (m.device.as_mut(), offset)))
+			.ok_or(BusError { addr })
+	}
+
+	pub fn read(&mut self, addr: u32, width: Width) -> Result<u32, BusError> {
+		let (device, offset) = self.device_at(addr)?;
+		device.read(offset, width).map_err(|_| BusError { addr })
+	}
+
+	pub fn write(&mut self, addr: u32, width: Width, value: u32) -> Result<(), BusError> {
+		let (device, offset) = self.device_at(addr)?;
+		device.write(offset, width, value).map_err(|_| BusError { addr })
+	}
+
+	/// Performs `i` with `value` as the contents of `i.reg`, returning the value loaded into it
+	pub fn access(&mut self, i: csr::Instruction, value: u32) -> Result<Option<u32>, BusError> {
+		match i.op.op {
+			LoadStore::Load => self.read(i.imm, i.op.width).map(Some),
+			LoadStore::Store => self.write(i.imm, i.op.width, value & i.op.width.to_mask()).map(|_| None),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::{LoadStoreOp, Register};
+	use crate::csr::registry::Registry;
+
+	/// Word registers that remember the last access
+	#[derive(Default)]
+	struct Scratch {
+		regs: [u32; 4],
+		last: Option<(u32, Width)>,
+	}
+
+	impl CsrDevice for Scratch {
+		fn read(&mut self, offset: u32, width: Width) -> Result<u32, BusError> {
+			self.last = Some((offset, width));
+			let reg = self.regs.get((offset / 4) as usize).ok_or(BusError { addr: offset })?;
+			Ok(reg & width.to_mask())
+		}
+
+		fn write(&mut self, offset: u32, width: Width, value: u32) -> Result<(), BusError> {
+			self.last = Some((offset, width));
+			let reg = self.regs.get_mut((offset / 4) as usize).ok_or(BusError { addr: offset })?;
+			*reg = value;
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn dispatch() {
+		let registry = Registry::builtin();
+		let mut bus = CsrBus::new();
+		bus.attach(&registry.blocks["isr"], Scratch::default()).unwrap();
+
+		bus.write(0x44, Width::Word, 0x1234).unwrap();
+		assert_eq!(bus.read(0x44, Width::Byte), Ok(0x34));
+		assert_eq!(bus.device::<Scratch>("isr").unwrap().last, Some((0x4, Width::Byte)));
+
+		let load = csr::Instruction {
+			op: LoadStoreOp { op: LoadStore::Load, width: Width::Short },
+			reg: Register::r1(),
+			imm: 0x44,
+		};
+		assert_eq!(bus.access(load, 0), Ok(Some(0x1234)));
+		let store = csr::Instruction {
+			op: LoadStoreOp { op: LoadStore::Store, width: Width::Byte },
+			..load
+		};
+		assert_eq!(bus.access(store, 0xabcd), Ok(None));
+		assert_eq!(bus.device::<Scratch>("isr").unwrap().regs[1], 0xcd);
+
+		// Device error, translated to the CSR address
+		assert_eq!(bus.read(0x50, Width::Word), Err(BusError { addr: 0x50 }));
+		// Unmapped
+		assert_eq!(bus.read(0x0, Width::Word), Err(BusError { addr: 0x0 }));
+		assert_eq!(bus.write(0x100, Width::Word, 0), Err(BusError { addr: 0x100 }));
+		assert!(bus.is_mapped(0xff));
+		assert!(!bus.is_mapped(0x100));
+	}
+
+	#[test]
+	fn attach() {
+		let registry = Registry::builtin();
+		let mut bus = CsrBus::new();
+		bus.attach(&registry.blocks["isr"], Scratch::default()).unwrap();
+		assert_eq!(bus.attach(&registry.blocks["isr"], Scratch::default()), Err(AttachError::Overlap {
+			block: "isr".into(),
+			existing: "isr".into(),
+		}));
+		bus.attach(&registry.blocks["psr"], Scratch::default()).unwrap();
+
+		assert!(bus.device::<Scratch>("dbg_out").is_none());
+
+		let mut top = Block {
+			name: "top".into(),
+			base: 0xffffffc0,
+			count: 2,
+			registers: Vec::new(),
+			relative_to: None,
+		};
+		assert_eq!(bus.attach(&top, Scratch::default()), Err(AttachError::PastEnd("top".into())));
+
+		// Unreachable by CSR instructions even though the addresses fit in a u32
+		let space = 1 << csr::IMM_BITS;
+		(top.base, top.count) = (space, 1);
+		assert_eq!(bus.attach(&top, Scratch::default()), Err(AttachError::PastEnd("top".into())));
+		(top.base, top.count) = (space - csr::regs::CSR_BLOCK_SIZE, 2);
+		assert_eq!(bus.attach(&top, Scratch::default()), Err(AttachError::PastEnd("top".into())));
+		top.count = 1;
+		bus.attach(&top, Scratch::default()).unwrap();
+		assert!(bus.is_mapped(space - 1));
+
+		bus.device_mut::<Scratch>("psr").unwrap().regs[0] = 7;
+		assert_eq!(bus.read(0x0, Width::Word), Ok(7));
+
+		assert!(bus.detach("psr").is_some());
+		assert!(bus.detach("psr").is_none());
+		assert!(!bus.is_mapped(0x0));
+	}
+}
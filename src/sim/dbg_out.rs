@@ -0,0 +1,243 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use log::warn;
+
+use crate::{
+	Width,
+	csr::regs::*,
+};
+
+use super::{BusError, CsrDevice};
+
+const STATUS: u32 = DBG_OUT_STATUS_REG - DBG_OUT_BASE;
+const CHAR_OUT0: u32 = DBG_OUT_CHAR_OUT0_REG - DBG_OUT_BASE;
+const CHAR_IN0: u32 = DBG_OUT_CHAR_IN0_REG - DBG_OUT_BASE;
+const BYTE_OUT0: u32 = DBG_OUT_BYTE_OUT0_REG - DBG_OUT_BASE;
+const BYTE_OUT3: u32 = DBG_OUT_BYTE_OUT3_REG - DBG_OUT_BASE;
+const GPIO_OUT0: u32 = DBG_OUT_GPIO_OUT0_REG - DBG_OUT_BASE;
+const GPIO_IN0: u32 = DBG_OUT_GPIO_IN0_REG - DBG_OUT_BASE;
+
+/// Model of the `dbg_out` block, offsets are relative to `DBG_OUT_BASE`
+///
+/// Characters written to `char_out0` go to the output and `char_in0` reads the next byte of the
+/// input, or zero once it's exhausted. Checking `char_in_ready` reads ahead one byte so it may
+/// block on an interactive input. Each `byte_out` register appends to its own capture buffer.
+pub struct DbgOut {
+	output: Box<dyn Write>,
+	input: Box<dyn Read>,
+	/// Byte read ahead from `input`
+	next: Option<u8>,
+	captured: [Vec<u8>; 4],
+	gpio_out: u32,
+	on_gpio_out: Box<dyn FnMut(u32)>,
+	on_gpio_in: Box<dyn FnMut() -> u32>,
+}
+
+impl fmt::Debug for DbgOut {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("DbgOut")
+			.field("next", &self.next)
+			.field("captured", &self.captured)
+			.field("gpio_out", &self.gpio_out)
+			.finish_non_exhaustive()
+	}
+}
+
+impl Default for DbgOut {
+	fn default() -> Self {
+		DbgOut::new()
+	}
+}
+
+impl DbgOut {
+	/// Device discarding output with no input and GPIO inputs reading as zero
+	pub fn new() -> DbgOut {
+		DbgOut {
+			output: Box::new(io::sink()),
+			input: Box::new(io::empty()),
+			next: None,
+			captured: Default::default(),
+			gpio_out: 0,
+			on_gpio_out: Box::new(|_| {}),
+			on_gpio_in: Box::new(|| 0),
+		}
+	}
+
+	/// Device connected to the host's stdout and stdin
+	pub fn stdio() -> DbgOut {
+		DbgOut::new()
+			.with_output(io::stdout())
+			.with_input(io::stdin())
+	}
+
+	pub fn with_output(mut self, output: impl Write + 'static) -> DbgOut {
+		self.output = Box::new(output);
+		self
+	}
+
+	pub fn with_input(mut self, input: impl Read + 'static) -> DbgOut {
+		self.input = Box::new(input);
+		self.next = None;
+		self
+	}
+
+	/// Called with the new value whenever `gpio_out0` is written
+	pub fn on_gpio_out(mut self, f: impl FnMut(u32) + 'static) -> DbgOut {
+		self.on_gpio_out = Box::new(f);
+		self
+	}
+
+	/// Called to sample the pins whenever `gpio_in0` is read
+	pub fn on_gpio_in(mut self, f: impl FnMut() -> u32 + 'static) -> DbgOut {
+		self.on_gpio_in = Box::new(f);
+		self
+	}
+
+	/// Bytes written to `byte_out<channel>`, `None` for channels the device doesn't have
+	pub fn captured(&self, channel: usize) -> Option<&[u8]> {
+		self.captured.get(channel).map(Vec::as_slice)
+	}
+
+	pub fn gpio_out(&self) -> u32 {
+		self.gpio_out
+	}
+
+	fn peek(&mut self) -> Option<u8> {
+		if self.next.is_none() {
+			let mut byte = [0];
+			match self.input.read(&mut byte) {
+				Ok(1) => self.next = Some(byte[0]),
+				Ok(_) => {},
+				Err(err) => warn!("dbg_out input failed: {err}"),
+			}
+		}
+		self.next
+	}
+
+	fn status(&mut self) -> u32 {
+		let mut status = DBG_OUT_STATUS_CHAR_OUT_READY_MASK;
+		if self.peek().is_some() {
+			status |= DBG_OUT_STATUS_CHAR_IN_READY_MASK;
+		}
+		status
+	}
+}
+
+impl CsrDevice for DbgOut {
+	/// Reads the register at `offset`, write only registers read as zero
+	fn read(&mut self, offset: u32, width: Width) -> Result<u32, BusError> {
+		let value = match offset {
+			STATUS => self.status(),
+			CHAR_IN0 => {
+				let value = self.peek().unwrap_or(0);
+				self.next = None;
+				value as u32
+			},
+			GPIO_OUT0 => self.gpio_out,
+			GPIO_IN0 => (self.on_gpio_in)(),
+			CHAR_OUT0 | BYTE_OUT0..=BYTE_OUT3 => 0,
+			_ => return Err(BusError { addr: offset }),
+		};
+		Ok(value & width.to_mask())
+	}
+
+	/// Writes the register at `offset`, writes to read only registers are ignored
+	fn write(&mut self, offset: u32, width: Width, value: u32) -> Result<(), BusError> {
+		let value = value & width.to_mask();
+		match offset {
+			CHAR_OUT0 => {
+				let result = self.output.write_all(&[value as u8]).and_then(|_| self.output.flush());
+				if let Err(err) = result {
+					warn!("dbg_out output failed: {err}");
+				}
+			},
+			BYTE_OUT0..=BYTE_OUT3 => self.captured[(offset - BYTE_OUT0) as usize].push(value as u8),
+			GPIO_OUT0 => {
+				self.gpio_out = value;
+				(self.on_gpio_out)(value);
+			},
+			STATUS | CHAR_IN0 | GPIO_IN0 => {},
+			_ => return Err(BusError { addr: offset }),
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use std::cell::{Cell, RefCell};
+	use std::rc::Rc;
+
+	/// Writer whose contents can be inspected after it's moved into a device
+	#[derive(Clone, Default)]
+	struct Shared(Rc<RefCell<Vec<u8>>>);
+
+	impl Write for Shared {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			self.0.borrow_mut().write(buf)
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn chars() {
+		let output = Shared::default();
+		let mut dev = DbgOut::new()
+			.with_output(output.clone())
+			.with_input(&b"ab"[..]);
+
+		dev.write(CHAR_OUT0, Width::Byte, 0x168).unwrap();
+		dev.write(CHAR_OUT0, Width::Byte, b'i' as u32).unwrap();
+		assert_eq!(*output.0.borrow(), b"hi");
+
+		assert_eq!(dev.read(STATUS, Width::Word), Ok(0b11));
+		assert_eq!(dev.read(CHAR_IN0, Width::Byte), Ok(b'a' as u32));
+		assert_eq!(dev.read(STATUS, Width::Word), Ok(0b11));
+		assert_eq!(dev.read(CHAR_IN0, Width::Byte), Ok(b'b' as u32));
+		assert_eq!(dev.read(STATUS, Width::Word), Ok(0b01));
+		assert_eq!(dev.read(CHAR_IN0, Width::Byte), Ok(0));
+		assert_eq!(dev.read(CHAR_OUT0, Width::Byte), Ok(0));
+	}
+
+	#[test]
+	fn bytes_and_gpio() {
+		let pins = Rc::new(Cell::new(0));
+		let driven = Rc::new(RefCell::new(Vec::new()));
+		let mut dev = DbgOut::new()
+			.on_gpio_in({
+				let pins = pins.clone();
+				move || pins.get()
+			})
+			.on_gpio_out({
+				let driven = driven.clone();
+				move |value| driven.borrow_mut().push(value)
+			});
+
+		dev.write(BYTE_OUT0, Width::Byte, 1).unwrap();
+		dev.write(BYTE_OUT0, Width::Byte, 2).unwrap();
+		dev.write(BYTE_OUT3, Width::Byte, 0xff).unwrap();
+		assert_eq!(dev.captured(0), Some(&[1, 2][..]));
+		assert_eq!(dev.captured(1), Some(&[][..]));
+		assert_eq!(dev.captured(3), Some(&[0xff][..]));
+		assert_eq!(dev.captured(4), None);
+
+		pins.set(0xa5a5);
+		assert_eq!(dev.read(GPIO_IN0, Width::Word), Ok(0xa5a5));
+		assert_eq!(dev.read(GPIO_IN0, Width::Byte), Ok(0xa5));
+
+		dev.write(GPIO_OUT0, Width::Word, 0x1234).unwrap();
+		dev.write(GPIO_OUT0, Width::Short, 0xffff_5678).unwrap();
+		assert_eq!(*driven.borrow(), [0x1234, 0x5678]);
+		assert_eq!(dev.read(GPIO_OUT0, Width::Word), Ok(0x5678));
+
+		dev.write(GPIO_IN0, Width::Word, 0).unwrap();
+		assert_eq!(dev.read(0x4, Width::Word), Err(BusError { addr: 0x4 }));
+		assert_eq!(dev.write(0x42, Width::Byte, 0), Err(BusError { addr: 0x42 }));
+	}
+}
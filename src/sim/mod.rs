@@ -0,0 +1,819 @@
+/* Copyright 2023 Robert Zieba, see LICENSE file for full license. */
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+use log::debug;
+
+use crate::{
+	BinOp,
+	Encode,
+	Flags,
+	Instruction,
+	LoadStore,
+	LoadStoreOp,
+	Register,
+	Width,
+	csr::{
+		self,
+		isr_save_slot,
+		registry::Registry,
+		regs::*,
+	},
+	memory,
+};
+
+mod bus;
+mod csr_bus;
+mod dbg_out;
+
+pub use bus::*;
+pub use csr_bus::*;
+pub use dbg_out::DbgOut;
+
+/// Reason an instruction couldn't complete, the PC is left pointing at the faulting instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Exception {
+	IllegalInstruction {
+		pc: u32,
+		value: u32,
+	},
+	Misaligned {
+		addr: u32,
+	},
+	Bus(BusError),
+}
+
+impl fmt::Display for Exception {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Exception::IllegalInstruction { pc, value } => write!(f, "illegal instruction {value:#010x} at {pc:#010x}"),
+			Exception::Misaligned { addr } => write!(f, "misaligned access at {addr:#010x}"),
+			Exception::Bus(err) => err.fmt(f),
+		}
+	}
+}
+
+impl std::error::Error for Exception {}
+
+impl From<BusError> for Exception {
+	fn from(err: BusError) -> Self {
+		Exception::Bus(err)
+	}
+}
+
+/// Event that transfers control to the handler at `ISR_BASE_REG`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trap {
+	Exception(Exception),
+	/// External interrupt with the given number
+	Interrupt(u32),
+}
+
+impl Trap {
+	pub const ILLEGAL_INSTRUCTION: u32 = 1;
+	pub const MISALIGNED: u32 = 2;
+	pub const BUS_ERROR: u32 = 3;
+	pub const INTERRUPT: u32 = 4;
+
+	/// Value reported in `ISR_ERR1_REG`
+	pub fn cause(&self) -> u32 {
+		match self {
+			Trap::Exception(Exception::IllegalInstruction { .. }) => Self::ILLEGAL_INSTRUCTION,
+			Trap::Exception(Exception::Misaligned { .. }) => Self::MISALIGNED,
+			Trap::Exception(Exception::Bus(_)) => Self::BUS_ERROR,
+			Trap::Interrupt(_) => Self::INTERRUPT,
+		}
+	}
+
+	/// Value reported in `ISR_ERR2_REG`: the instruction word, the faulting address or the interrupt number
+	pub fn detail(&self) -> u32 {
+		match self {
+			Trap::Exception(Exception::IllegalInstruction { value, .. }) => *value,
+			Trap::Exception(Exception::Misaligned { addr }) => *addr,
+			Trap::Exception(Exception::Bus(err)) => err.addr,
+			Trap::Interrupt(number) => *number,
+		}
+	}
+}
+
+/// Reference model of a BIBE core
+///
+/// `r0` always reads as zero and `r31` is the PC. While an instruction executes the PC
+/// already points at the next instruction, writing it branches. Flags live in `PSR_PSR0_REG`.
+///
+/// Traps are delivered by `step` once `ISR_BASE_REG` is non-zero, before that exceptions are
+/// returned to the caller and interrupts stay pending. Taking a trap:
+///
+/// 1. Banks `r1` to `r31` into their `isr_save_slot`, `ISR_PC_REG` gets the faulting instruction
+///    for exceptions and the next instruction to run for interrupts
+/// 2. Sets `ISR_ERR1_REG` and `ISR_ERR2_REG` to `Trap::cause` and `Trap::detail`
+/// 3. Increments the nesting depth in `ISR_ENTER_REG` and jumps to `ISR_BASE_REG`
+///
+/// Any store to `ISR_EXIT_REG` reloads `r1` to `r31` from the save slots, resuming at
+/// `ISR_PC_REG`, and decrements the depth. Exceptions nest, overwriting the save slots, so a
+/// handler that can fault must keep the interrupted frame elsewhere first. Interrupts are only
+/// delivered at depth zero. The PSR isn't banked.
+///
+/// CSR accesses go to the device `csr_bus` has attached at that address, CSRs without a device
+/// behind them are plain byte addressed storage starting at their reset values from the registry.
+/// The `psr` and `isr` blocks belong to the core, devices attached
+/// over them are never accessed. Stores to read only and loads from write only CSRs in the built-in layout
+/// raise a bus error at the accessed address.
+#[derive(Debug)]
+pub struct Cpu<B: Bus> {
+	regs: [u32; 32],
+	/// CSRs without a device, keyed by word aligned address
+	csrs: HashMap<u32, u32>,
+	/// Interrupts waiting for depth zero
+	pending: VecDeque<u32>,
+	/// Layout CSR accesses are checked against
+	registry: Arc<Registry>,
+	pub bus: B,
+	/// Devices handling CSR accesses
+	pub csr_bus: CsrBus,
+}
+
+impl<B: Bus> Cpu<B> {
+	/// CPU using the built-in CSR layout, which is only parsed once for all of them
+	pub fn new(bus: B) -> Cpu<B> {
+		static BUILTIN: OnceLock<Arc<Registry>> = OnceLock::new();
+		Cpu::with_registry(bus, BUILTIN.get_or_init(|| Arc::new(Registry::builtin())).clone())
+	}
+
+	/// CPU checking CSR accesses against `registry`, with its registers at their reset values
+	pub fn with_registry(bus: B, registry: Arc<Registry>) -> Cpu<B> {
+		let mut cpu = Cpu {
+			regs: [0; 32],
+			csrs: HashMap::new(),
+			pending: VecDeque::new(),
+			registry,
+			bus,
+			csr_bus: CsrBus::new(),
+		};
+
+		let registry = cpu.registry.clone();
+		for reg in registry.iter() {
+			cpu.write_csr(reg.address(), reg.register.width, reg.register.reset);
+		}
+		cpu
+	}
+
+	pub fn reg(&self, reg: Register) -> u32 {
+		self.regs[reg.as_u8() as usize]
+	}
+
+	pub fn set_reg(&mut self, reg: Register, value: u32) {
+		if reg != Register::z() {
+			self.regs[reg.as_u8() as usize] = value;
+		}
+	}
+
+	pub fn pc(&self) -> u32 {
+		self.reg(Register::pc())
+	}
+
+	pub fn set_pc(&mut self, pc: u32) {
+		self.set_reg(Register::pc(), pc);
+	}
+
+	pub fn flags(&self) -> Flags {
+		Flags::from_psr(self.csr(PSR_PSR0_REG))
+	}
+
+	pub fn set_flags(&mut self, flags: Flags) {
+		let psr = flags.to_psr(self.csr(PSR_PSR0_REG));
+		self.set_csr(PSR_PSR0_REG, psr);
+	}
+
+	/// Word of CSR storage at `addr`, bypassing any device
+	pub fn csr(&self, addr: u32) -> u32 {
+		self.read_csr(addr, Width::Word)
+	}
+
+	pub fn set_csr(&mut self, addr: u32, value: u32) {
+		self.write_csr(addr, Width::Word, value);
+	}
+
+	fn csr_byte(&self, addr: u32) -> u8 {
+		let word = self.csrs.get(&(addr & !3)).copied().unwrap_or(0);
+		(word >> (8 * (addr % 4))) as u8
+	}
+
+	fn set_csr_byte(&mut self, addr: u32, value: u8) {
+		let shift = 8 * (addr % 4);
+		let word = self.csrs.entry(addr & !3).or_insert(0);
+		*word = (*word & !(0xff << shift)) | (value as u32) << shift;
+	}
+
+	/// Little endian like memory, so narrower accesses alias the bytes of the word they're in
+	fn read_csr(&self, addr: u32, width: Width) -> u32 {
+		(0..width.to_len()).rev().fold(0, |value, i| value << 8 | self.csr_byte(addr.wrapping_add(i)) as u32)
+	}
+
+	fn write_csr(&mut self, addr: u32, width: Width, value: u32) {
+		for i in 0..width.to_len() {
+			self.set_csr_byte(addr.wrapping_add(i), (value >> (8 * i)) as u8);
+		}
+	}
+
+	/// Number of traps currently being handled
+	pub fn depth(&self) -> u32 {
+		self.csr(ISR_ENTER_REG)
+	}
+
+	/// Raises external interrupt `number`, it's taken by a later `step`
+	pub fn interrupt(&mut self, number: u32) {
+		self.pending.push_back(number);
+	}
+
+	/// Enters the trap handler
+	pub fn trap(&mut self, trap: Trap) {
+		for reg in (1..32).filter_map(Register::new) {
+			let slot = isr_save_slot(reg).unwrap();
+			self.set_csr(slot, self.reg(reg));
+		}
+
+		self.set_csr(ISR_ERR1_REG, trap.cause());
+		self.set_csr(ISR_ERR2_REG, trap.detail());
+		self.set_csr(ISR_ENTER_REG, self.depth() + 1);
+		self.set_pc(self.csr(ISR_BASE_REG));
+	}
+
+	fn exit_trap(&mut self) {
+		for reg in (1..32).filter_map(Register::new) {
+			let slot = isr_save_slot(reg).unwrap();
+			self.set_reg(reg, self.csr(slot));
+		}
+		self.set_csr(ISR_ENTER_REG, self.depth().saturating_sub(1));
+	}
+
+	fn check_alignment(addr: u32, width: Width) -> Result<(), Exception> {
+		if !addr.is_multiple_of(width.to_len()) {
+			Err(Exception::Misaligned { addr })
+		} else {
+			Ok(())
+		}
+	}
+
+	fn alu(&mut self, op: BinOp, dest: Register, lhs: u32, rhs: u32) {
+		let (result, flags) = op.eval(lhs, rhs, self.flags().carry);
+		if op.is_cc() {
+			self.set_flags(flags);
+		}
+		self.set_reg(dest, result);
+	}
+
+	fn memory(&mut self, op: LoadStoreOp, rd: Register, addr: u32) -> Result<(), Exception> {
+		Self::check_alignment(addr, op.width)?;
+		match op.op {
+			LoadStore::Load => {
+				let value = self.bus.read(addr, op.width)?;
+				self.set_reg(rd, value);
+			},
+			LoadStore::Store => {
+				let value = self.reg(rd) & op.width.to_mask();
+				self.bus.write(addr, op.width, value)?;
+			},
+		}
+		Ok(())
+	}
+
+	fn csr_access(&mut self, i: csr::Instruction) -> Result<(), Exception> {
+		if i.check_access(&self.registry).is_err() {
+			return Err(BusError { addr: i.imm }.into());
+		}
+
+		let core = (PSR_BASE..PSR_BASE + PSR_SIZE).contains(&i.imm)
+			|| (ISR_BASE..ISR_BASE + ISR_SIZE).contains(&i.imm);
+		if !core && self.csr_bus.is_mapped(i.imm) {
+			if let Some(value) = self.csr_bus.access(i, self.reg(i.reg))? {
+				self.set_reg(i.reg, value);
+			}
+			return Ok(());
+		}
+
+		let width = i.op.width;
+		match i.op.op {
+			LoadStore::Load => self.set_reg(i.reg, self.read_csr(i.imm, width)),
+			LoadStore::Store => match i.imm {
+				ISR_EXIT_REG => self.exit_trap(),
+				addr => self.write_csr(addr, width, self.reg(i.reg)),
+			},
+		}
+		Ok(())
+	}
+
+	/// Executes `instruction` as if it had been fetched from `pc`
+	pub fn execute(&mut self, pc: u32, instruction: Instruction) -> Result<(), Exception> {
+		self.set_pc(pc.wrapping_add(4));
+		let result = match instruction {
+			Instruction::Rrr(i) => {
+				let rhs = i.shift.apply(self.reg(i.rhs));
+				self.alu(i.op, i.dest, self.reg(i.lhs), rhs);
+				Ok(())
+			},
+			Instruction::Rri(i) => {
+				if i.cond.holds(&self.flags()) {
+					self.alu(i.op, i.dest, self.reg(i.src), i.imm as i32 as u32);
+				}
+				Ok(())
+			},
+			Instruction::Memory(memory::Instruction::Rr(i)) => {
+				let addr = self.reg(i.rs).wrapping_add(i.shift.apply(self.reg(i.rq)));
+				self.memory(i.op, i.rd, addr)
+			},
+			Instruction::Memory(memory::Instruction::Ri(i)) => {
+				let addr = self.reg(i.rs).wrapping_add(i.imm as i32 as u32);
+				self.memory(i.op, i.rd, addr)
+			},
+			Instruction::Csr(i) => self.csr_access(i),
+			Instruction::Jump(i) => {
+				self.set_pc(pc.wrapping_add(i.imm as u32));
+				Ok(())
+			},
+			Instruction::Reserved0010(_)
+			| Instruction::Reserved0011(_) => Err(Exception::IllegalInstruction {
+				pc,
+				value: instruction.encode(),
+			}),
+		};
+
+		if result.is_err() {
+			self.set_pc(pc);
+		}
+		result
+	}
+
+	/// Takes a pending interrupt or executes a single instruction, trapping on exceptions if a handler is installed
+	pub fn step(&mut self) -> Result<(), Exception> {
+		let handler = self.csr(ISR_BASE_REG) != 0;
+		if handler && self.depth() == 0 {
+			if let Some(number) = self.pending.pop_front() {
+				self.trap(Trap::Interrupt(number));
+				return Ok(());
+			}
+		}
+
+		match self.fetch_execute() {
+			Err(exception) if handler => {
+				debug!("Trap: {exception}");
+				self.trap(Trap::Exception(exception));
+				Ok(())
+			},
+			result => result,
+		}
+	}
+
+	fn fetch_execute(&mut self) -> Result<(), Exception> {
+		let pc = self.pc();
+		Self::check_alignment(pc, Width::Word)?;
+
+		let value = self.bus.read(pc, Width::Word)?;
+		let instruction = Instruction::decode(value).map_err(|err| {
+			debug!("Failed to decode: {err}");
+			Exception::IllegalInstruction { pc, value }
+		})?;
+
+		self.execute(pc, instruction)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::asm::{Assembler, Program};
+	use crate::csr::registry::Registry;
+
+	fn program(source: &str) -> (Cpu<Ram>, Program) {
+		let program = Assembler::new().with_registry(Registry::builtin()).assemble(source).unwrap();
+		let mut ram = Ram::new(0, 0x1000);
+		ram.load_words(program.origin, &program.words).unwrap();
+
+		let mut cpu = Cpu::new(ram);
+		cpu.set_pc(program.origin);
+		(cpu, program)
+	}
+
+	fn cpu(source: &str) -> Cpu<Ram> {
+		program(source).0
+	}
+
+	fn label(program: &Program, name: &str) -> u32 {
+		program.symbols[name].value() as u32
+	}
+
+	fn word(cpu: &mut Cpu<Ram>, addr: u32) -> u32 {
+		cpu.bus.read(addr, Width::Word).unwrap()
+	}
+
+	fn run(cpu: &mut Cpu<Ram>, steps: usize) {
+		for _ in 0..steps {
+			cpu.step().unwrap();
+		}
+	}
+
+	#[test]
+	fn arithmetic() {
+		let mut cpu = cpu("
+			add r1, r0, 12
+			add r2, r0, -3
+			mul r3, r1, r2
+			sub r4, r1, r2 << 2
+			div r5, r3, r2
+			mod r6, r1, 5
+			add r0, r1, r1
+			add r7, pc, 0
+		");
+		run(&mut cpu, 8);
+
+		assert_eq!(cpu.reg(Register::r1()), 12);
+		assert_eq!(cpu.reg(Register::r2()), -3i32 as u32);
+		assert_eq!(cpu.reg(Register::r3()), -36i32 as u32);
+		assert_eq!(cpu.reg(Register::r4()), 24);
+		assert_eq!(cpu.reg(Register::r5()), 12);
+		assert_eq!(cpu.reg(Register::r6()), 2);
+		assert_eq!(cpu.reg(Register::r0()), 0);
+		assert_eq!(cpu.reg(Register::r7()), 0x20);
+		assert_eq!(cpu.flags(), Flags::default());
+	}
+
+	#[test]
+	fn conditional_loop() {
+		// Sum 1 to 10
+		let mut cpu = cpu("
+			add r1, r0, 10
+			add r2, r0, 0
+		loop:
+			add r2, r2, r1
+			subcc r1, r1, 1
+			add.z pc, pc, 4
+			j loop
+			add r3, r0, 1
+		end:
+			j end
+		");
+		run(&mut cpu, 2 + 10 * 4 + 1);
+
+		assert_eq!(cpu.reg(Register::r1()), 0);
+		assert_eq!(cpu.reg(Register::r2()), 55);
+		assert_eq!(cpu.reg(Register::r3()), 1);
+		assert!(cpu.flags().zero);
+		assert_eq!(cpu.pc(), 0x1c);
+	}
+
+	#[test]
+	fn call() {
+		let mut cpu = cpu("
+			add r2, r0, 5
+			call square
+			add r3, r2, 0
+		end:
+			j end
+		square:
+			mul r2, r2, r2
+			add pc, lr, 0
+		");
+		run(&mut cpu, 1 + 2 + 2 + 1);
+
+		assert_eq!(cpu.reg(Register::r3()), 25);
+		assert_eq!(cpu.reg(Register::lr()), 0xc);
+		assert_eq!(cpu.pc(), 0x10);
+	}
+
+	#[test]
+	fn indirect_call() {
+		let mut cpu = cpu("
+			add r5, r0, table
+			ld.w r6, [r5 + 4]
+			jalr lr, r6, 0
+			add r3, r2, 0
+		end:
+			j end
+		negate:
+			sub r2, r0, r1
+			ret
+		double:
+			add r2, r1, r1
+			ret
+		table:
+			.word negate, double
+		");
+		cpu.set_reg(Register::r1(), 21);
+		run(&mut cpu, 2 + 2 + 2 + 1);
+
+		assert_eq!(cpu.reg(Register::r3()), 42);
+		assert_eq!(cpu.pc(), 0x14);
+	}
+
+	#[test]
+	fn load_store() {
+		let mut cpu = cpu("
+			add r1, r0, data
+			ld.w r2, [r1]
+			st.b r2, [r1 + 4]
+			add r3, r0, 1
+			st.s r2, [r1 + r3 << 1]
+			ld.w r4, [r1 + 4]
+			ld.b r5, [r1 - 1]
+			.align 16
+		data:
+			.word 0x12345678, 0xffffffff
+		");
+		run(&mut cpu, 7);
+
+		assert_eq!(cpu.reg(Register::r2()), 0x12345678);
+		assert_eq!(cpu.reg(Register::r4()), 0xffffff78);
+		assert_eq!(cpu.bus.read(0x20, Width::Word), Ok(0x56785678));
+		assert_eq!(cpu.reg(Register::r5()), 0);
+	}
+
+	#[test]
+	fn csr() {
+		let mut cpu = cpu("
+			add r1, r0, -1
+			csr.st.b r1, 0x201
+			csr.ld r2, 0x201
+			csr.st r1, 0x80
+			csr.ld.s r3, 0x80
+			csr.st r1, isr.err1
+			csr.ld.b r4, 0x53
+		");
+		run(&mut cpu, 5);
+
+		assert_eq!(cpu.reg(Register::r2()), 0xff);
+		assert_eq!(cpu.reg(Register::r3()), 0xffff);
+		assert_eq!(cpu.csr(0x80), 0xffffffff);
+
+		assert_eq!(cpu.step(), Err(Exception::Bus(BusError { addr: ISR_ERR1_REG })));
+		assert_eq!(cpu.csr(ISR_ERR1_REG), 0);
+		cpu.set_pc(cpu.pc() + 4);
+		assert_eq!(cpu.step(), Err(Exception::Bus(BusError { addr: 0x53 })));
+	}
+
+	#[test]
+	fn csr_storage() {
+		let mut cpu = cpu("
+			csr.ld r1, dbg_out.status
+			add r2, r0, 0x5a
+			csr.st.b r2, 0x205
+			csr.ld r3, 0x204
+			csr.ld.s r4, 0x205
+			add r2, r0, 0x41
+			csr.st.b r2, dbg_out.char_out0
+		");
+		run(&mut cpu, 7);
+
+		// Registry reset values without a device behind them
+		assert_eq!(cpu.reg(Register::r1()), 1);
+
+		// Sub-word accesses share the bytes of the word they're in
+		assert_eq!(cpu.reg(Register::r3()), 0x5a00);
+		assert_eq!(cpu.reg(Register::r4()), 0x5a);
+		assert_eq!(cpu.csr(DBG_OUT_CHAR_OUT0_REG), 0x41);
+		assert_eq!(cpu.csr(0x204), 0x5a00);
+
+		// The built-in layout is only parsed once
+		assert!(Arc::ptr_eq(&cpu.registry, &Cpu::new(Ram::new(0, 4)).registry));
+	}
+
+	#[test]
+	fn psr() {
+		let mut cpu = cpu("
+			add r1, r0, 1
+			subcc r2, r0, r1
+			csr.ld r3, 0x0
+			csr.st r0, 0x0
+			add.n r4, r0, 1
+			add r5, r0, 0xa
+			csr.st r5, 0x0
+			add.c r6, r0, 1
+		");
+		run(&mut cpu, 8);
+
+		assert_eq!(cpu.reg(Register::r3()), 0b1010);
+		assert_eq!(cpu.reg(Register::r4()), 0);
+		assert_eq!(cpu.reg(Register::r6()), 1);
+		assert!(cpu.flags().negative);
+	}
+
+	#[test]
+	fn exceptions() {
+		let mut cpu = cpu("
+			.word 0x20000000
+			ld.w r1, [r0 + 2]
+			ld.w r1, [r0 + 4092]
+			ld.w r1, [r0 - 4]
+		");
+
+		assert_eq!(cpu.step(), Err(Exception::IllegalInstruction { pc: 0, value: 0x20000000 }));
+		assert_eq!(cpu.pc(), 0);
+
+		cpu.set_pc(4);
+		assert_eq!(cpu.step(), Err(Exception::Misaligned { addr: 2 }));
+		assert_eq!(cpu.pc(), 4);
+
+		cpu.set_pc(8);
+		cpu.step().unwrap();
+
+		assert_eq!(cpu.step(), Err(Exception::Bus(BusError { addr: 0xfffffffc })));
+		assert_eq!(cpu.pc(), 12);
+
+		cpu.set_pc(0x2000);
+		assert_eq!(cpu.step(), Err(Exception::Bus(BusError { addr: 0x2000 })));
+	}
+
+	#[test]
+	fn trap() {
+		let (mut cpu, program) = program("
+			add r1, r0, handler
+			csr.st r1, isr.base
+			add r1, r0, 7
+			.word 0x20000000
+			ld.w r2, [r0 + 2]
+			add r3, r1, 1
+		done:
+			j done
+		handler:
+			add r1, r0, 99
+			csr.ld r20, isr.err1
+			csr.ld r21, isr.err2
+			st.w r20, [r0 + cause]
+			st.w r21, [r0 + detail]
+			csr.ld r20, isr.pc
+			add r20, r20, 4
+			csr.st r20, isr.pc
+			csr.st r0, isr.exit
+		cause: .word 0
+		detail: .word 0
+		");
+		let handler = label(&program, "handler");
+		let (cause, detail) = (label(&program, "cause"), label(&program, "detail"));
+
+		run(&mut cpu, 4);
+		assert_eq!(cpu.pc(), handler);
+		assert_eq!(cpu.depth(), 1);
+		assert_eq!(cpu.csr(ISR_PC_REG), 0xc);
+		assert_eq!(cpu.csr(ISR_R1_REG), 7);
+
+		// Back to back, the next instruction faults as soon as the first handler returns
+		run(&mut cpu, 9);
+		assert_eq!(word(&mut cpu, cause), Trap::ILLEGAL_INSTRUCTION);
+		assert_eq!(word(&mut cpu, detail), 0x20000000);
+		assert_eq!(cpu.depth(), 0);
+		assert_eq!(cpu.pc(), 0x10);
+		assert_eq!(cpu.reg(Register::r1()), 7);
+
+		run(&mut cpu, 1);
+		assert_eq!(cpu.pc(), handler);
+		run(&mut cpu, 9);
+		assert_eq!(word(&mut cpu, cause), Trap::MISALIGNED);
+		assert_eq!(word(&mut cpu, detail), 2);
+		assert_eq!(cpu.pc(), 0x14);
+
+		run(&mut cpu, 2);
+		assert_eq!(cpu.reg(Register::r3()), 8);
+		assert_eq!(cpu.reg(Register::r2()), 0);
+		assert_eq!(cpu.pc(), label(&program, "done"));
+	}
+
+	#[test]
+	fn nested_trap() {
+		let (mut cpu, program) = program("
+			add r1, r0, handler
+			csr.st r1, isr.base
+			add r1, r0, 7
+			.word 0x20000000
+			add r2, r1, 1
+		done:
+			j done
+		handler:
+			csr.ld r20, isr.enter
+			subcc r0, r20, 1
+			add.nz pc, r0, inner
+			; Keep the interrupted frame in registers, they're banked by the nested trap
+			csr.ld r21, isr.pc
+			csr.ld r22, isr.r1
+			add r1, r0, 99
+			.word 0x20000000
+			st.w r1, [r0 + outer_r1]
+			add r21, r21, 4
+			csr.st r21, isr.pc
+			csr.st r22, isr.r1
+			csr.st r0, isr.exit
+		inner:
+			st.w r20, [r0 + inner_depth]
+			csr.ld r21, isr.pc
+			add r21, r21, 4
+			csr.st r21, isr.pc
+			add r1, r0, 5
+			csr.st r0, isr.exit
+		inner_depth: .word 0
+		outer_r1: .word 0
+		");
+
+		for _ in 0..32 {
+			if cpu.pc() == label(&program, "done") {
+				break;
+			}
+			cpu.step().unwrap();
+		}
+
+		assert_eq!(cpu.pc(), label(&program, "done"));
+		assert_eq!(cpu.depth(), 0);
+		assert_eq!(word(&mut cpu, label(&program, "inner_depth")), 2);
+		assert_eq!(word(&mut cpu, label(&program, "outer_r1")), 99);
+		assert_eq!(cpu.reg(Register::r1()), 7);
+		assert_eq!(cpu.reg(Register::r2()), 8);
+	}
+
+	#[test]
+	fn interrupts() {
+		let (mut cpu, program) = program("
+			add r1, r0, handler
+			add r2, r0, 0
+		loop:
+			add r2, r2, 1
+			j loop
+		handler:
+			csr.ld r20, isr.err2
+			ld.w r21, [r0 + count]
+			add r21, r21, 1
+			st.w r21, [r0 + count]
+			st.w r20, [r0 + last]
+			csr.st r0, isr.exit
+		count: .word 0
+		last: .word 0
+		");
+		let handler = label(&program, "handler");
+		let (count, last) = (label(&program, "count"), label(&program, "last"));
+
+		// No handler installed yet, the interrupt waits
+		cpu.interrupt(5);
+		run(&mut cpu, 3);
+		assert_eq!(cpu.reg(Register::r2()), 1);
+		assert_eq!(cpu.depth(), 0);
+
+		cpu.set_csr(ISR_BASE_REG, handler);
+		cpu.interrupt(6);
+		run(&mut cpu, 1);
+		assert_eq!(cpu.pc(), handler);
+		assert_eq!(cpu.csr(ISR_ERR1_REG), Trap::INTERRUPT);
+		assert_eq!(cpu.csr(ISR_PC_REG), label(&program, "loop") + 4);
+
+		// Masked while handling 5, 6 is taken straight after the exit
+		run(&mut cpu, 6);
+		assert_eq!(word(&mut cpu, last), 5);
+		assert_eq!(cpu.depth(), 0);
+		run(&mut cpu, 1);
+		assert_eq!(cpu.pc(), handler);
+		assert_eq!(cpu.depth(), 1);
+		run(&mut cpu, 6);
+		assert_eq!(word(&mut cpu, last), 6);
+		assert_eq!(word(&mut cpu, count), 2);
+
+		run(&mut cpu, 2);
+		assert_eq!(cpu.reg(Register::r2()), 2);
+	}
+
+	#[test]
+	fn dbg_out() {
+		let mut cpu = cpu("
+			add r1, r0, 0
+		echo:
+			csr.ld r2, dbg_out.status
+			and r2, r2, 2
+			subcc r0, r2, 0
+			add.z pc, r0, done
+			csr.ld.b r3, dbg_out.char_in0
+			csr.st.b r3, dbg_out.char_out0
+			csr.st.b r3, dbg_out.byte_out1
+			add r1, r1, 1
+			j echo
+		done:
+			csr.st r1, dbg_out.gpio_out0
+			csr.ld r4, dbg_out.gpio_in0
+			csr.ld r5, 0x104
+		");
+		let dbg_out = DbgOut::new()
+			.with_input(&b"ok"[..])
+			.on_gpio_in(|| 0x200);
+		cpu.csr_bus.attach(&Registry::builtin().blocks["dbg_out"], dbg_out).unwrap();
+
+		run(&mut cpu, 1 + 2 * 9 + 4 + 2);
+		assert_eq!(cpu.reg(Register::r1()), 2);
+		assert_eq!(cpu.reg(Register::r4()), 0x200);
+
+		let dev = cpu.csr_bus.device::<DbgOut>("dbg_out").unwrap();
+		assert_eq!(dev.captured(1), Some(&b"ok"[..]));
+		assert_eq!(dev.gpio_out(), 2);
+
+		assert_eq!(cpu.step(), Err(Exception::Bus(BusError { addr: 0x104 })));
+
+		// Core CSRs are never routed to a device
+		cpu.csr_bus.attach(&Registry::builtin().blocks["isr"], DbgOut::new()).unwrap();
+		cpu.set_reg(Register::r1(), 5);
+		cpu.execute(0, crate::asm::parse_line("csr.st r1, 0x80").unwrap()).unwrap();
+		assert_eq!(cpu.csr(ISR_R1_REG), 5);
+	}
+}
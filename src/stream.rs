@@ -0,0 +1,112 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+use crate::{
+	DecodeError,
+	Encode,
+	Endian,
+	Instruction,
+};
+
+/// Iterator over the instruction words in a byte buffer, see `decode_stream`
+#[derive(Clone, Debug)]
+pub struct DecodeStream<'a> {
+	words: std::slice::ChunksExact<'a, u8>,
+	endian: Endian,
+	addr: u32,
+}
+
+impl<'a> DecodeStream<'a> {
+	/// Bytes after the last whole word, these aren't decoded
+	pub fn remainder(&self) -> &'a [u8] {
+		self.words.remainder()
+	}
+}
+
+impl Iterator for DecodeStream<'_> {
+	type Item = (u32, Result<Instruction, DecodeError>);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let word = self.words.next()?;
+		let addr = self.addr;
+		self.addr = self.addr.wrapping_add(4);
+
+		let value = self.endian.read_word(word.try_into().unwrap());
+		Some((addr, Instruction::decode(value)))
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		self.words.size_hint()
+	}
+}
+
+impl ExactSizeIterator for DecodeStream<'_> {}
+
+/// Decodes each word of `bytes`, the first of which is at `base_addr`
+///
+/// Words that don't decode are reported and skipped over so data mixed into code doesn't end
+/// the stream. A partial word at the end is left in `DecodeStream::remainder`.
+pub fn decode_stream(bytes: &[u8], endian: Endian, base_addr: u32) -> DecodeStream<'_> {
+	DecodeStream {
+		words: bytes.chunks_exact(4),
+		endian,
+		addr: base_addr,
+	}
+}
+
+impl Instruction {
+	/// Appends the encoding to `bytes`
+	pub fn encode_into(&self, bytes: &mut Vec<u8>, endian: Endian) {
+		bytes.extend_from_slice(&endian.write_word(self.encode()));
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn round_trip() {
+		let program: Vec<Instruction> = ["add r1, r0, 12", "ld.w r2, [r1 - 4]", "j .-8", "ret"].iter()
+			.map(|text| text.parse().unwrap())
+			.collect();
+
+		for endian in [Endian::Little, Endian::Big] {
+			let mut bytes = Vec::new();
+			for i in &program {
+				i.encode_into(&mut bytes, endian);
+			}
+			assert_eq!(bytes.len(), 16);
+
+			let decoded: Vec<_> = decode_stream(&bytes, endian, 0x100).collect();
+			let expected: Vec<_> = (0x100..).step_by(4).zip(program.iter().map(|i| Ok(*i))).collect();
+			assert_eq!(decoded, expected);
+		}
+
+		let mut bytes = Vec::new();
+		program[2].encode_into(&mut bytes, Endian::Big);
+		assert_eq!(bytes, Endian::Big.write_word(program[2].encode()));
+		assert_eq!(bytes[0] >> 6, 0b11);
+	}
+
+	#[test]
+	fn bad_words() {
+		let mut bytes = Vec::new();
+		let nop = crate::asm::nop();
+		nop.encode_into(&mut bytes, Endian::Little);
+		// An rrr word with an unknown operation
+		bytes.extend_from_slice(&0x0f80_0000_u32.to_le_bytes());
+		nop.encode_into(&mut bytes, Endian::Little);
+		bytes.extend_from_slice(b"hi");
+
+		let mut stream = decode_stream(&bytes, Endian::Little, 0xffff_fff8);
+		assert_eq!(stream.len(), 3);
+		assert_eq!(stream.next(), Some((0xffff_fff8, Ok(nop))));
+		let (addr, result) = stream.next().unwrap();
+		assert_eq!(addr, 0xffff_fffc);
+		assert!(result.is_err());
+		assert_eq!(stream.next(), Some((0, Ok(nop))));
+		assert_eq!(stream.next(), None);
+		assert_eq!(stream.remainder(), b"hi");
+
+		assert_eq!(decode_stream(b"abc", Endian::Big, 0).count(), 0);
+	}
+}
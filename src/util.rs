@@ -1,3 +1,4 @@
+use std::fmt;
 use std::ops::Add;
 
 /* Copyright 2023 Robert Zieba, see LICENSE file for full license. */
@@ -40,6 +41,41 @@ impl Width {
 	}
 }
 
+impl fmt::Display for Width {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let suffix = match self {
+			Width::Byte => "b",
+			Width::Short => "s",
+			Width::Word => "w",
+		};
+		f.write_str(suffix)
+	}
+}
+
+/// Byte order of instruction words in memory
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Endian {
+	#[default]
+	Little,
+	Big,
+}
+
+impl Endian {
+	pub fn read_word(self, bytes: [u8; 4]) -> u32 {
+		match self {
+			Endian::Little => u32::from_le_bytes(bytes),
+			Endian::Big => u32::from_be_bytes(bytes),
+		}
+	}
+
+	pub fn write_word(self, value: u32) -> [u8; 4] {
+		match self {
+			Endian::Little => value.to_le_bytes(),
+			Endian::Big => value.to_be_bytes(),
+		}
+	}
+}
+
 impl Add<Width> for u32 {
 	type Output = u32;
 
@@ -75,6 +111,14 @@ mod test {
 		assert_eq!(4 + Width::Word, 8);
 	}
 
+	#[test]
+	fn endian() {
+		assert_eq!(Endian::Little.read_word([1, 2, 3, 4]), 0x04030201);
+		assert_eq!(Endian::Big.read_word([1, 2, 3, 4]), 0x01020304);
+		assert_eq!(Endian::Little.write_word(0x04030201), [1, 2, 3, 4]);
+		assert_eq!(Endian::Big.write_word(0x04030201), [4, 3, 2, 1]);
+	}
+
 	#[test]
 	fn width_to_mask() {
 		assert_eq!(Width::to_mask(Width::Byte), 0xff);